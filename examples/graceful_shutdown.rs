@@ -2,7 +2,7 @@ use h2x::{
     http::{HeaderValue, Method, StatusCode},
     *,
 };
-use std::{fs, future::Future, io::Result, net::SocketAddr, pin::pin, task::Poll};
+use std::{future::Future, io::Result, net::SocketAddr, pin::pin, task::Poll};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -58,21 +58,9 @@ async fn handler(addr: SocketAddr, req: Request, mut res: Response) -> h2x::Resu
     res.headers
         .append("access-control-allow-origin", HeaderValue::from_static("*"));
 
-    res.headers
-        .append("content-type", HeaderValue::from_static("text/html"));
-
     match (req.method.clone(), req.uri.path()) {
-        (Method::GET, "/") => res.write(fs::read("examples/index.html").unwrap()).await,
-        (Method::GET, "/test") => {
-            let body = format!("{req:#?}");
-            res.headers
-                .append("content-length", HeaderValue::from(body.len()));
-
-            res.write(body).await
-        }
-        _ => {
-            res.status = StatusCode::NOT_FOUND;
-            res.write(format!("{req:#?}")).await
-        }
+        (Method::GET, "/") => ServeFile::new("examples/index.html").serve(&req, res).await,
+        (Method::GET, "/test") => res.text(format!("{req:#?}")).await,
+        _ => res.error(StatusCode::NOT_FOUND, format!("{req:#?}")).await,
     }
 }
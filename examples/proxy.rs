@@ -0,0 +1,71 @@
+use h2x::{
+    http::{HeaderValue, Method, StatusCode},
+    *,
+};
+use std::{io, net::SocketAddr};
+use tokio::{io::copy_bidirectional, net::TcpStream};
+
+// base64("user:pass")
+const CREDENTIALS: &str = "Basic dXNlcjpwYXNz";
+
+#[derive(Clone)]
+struct Proxy {
+    addr: SocketAddr,
+}
+
+impl Incoming for Proxy {
+    async fn stream(self, req: Request, mut res: Response) {
+        if req.method != Method::CONNECT {
+            res.status = StatusCode::METHOD_NOT_ALLOWED;
+            let _ = res.send_headers();
+            return;
+        }
+        let authorized = req
+            .headers
+            .get("proxy-authorization")
+            .is_some_and(|v| v == CREDENTIALS);
+
+        if !authorized {
+            res.status = StatusCode::PROXY_AUTHENTICATION_REQUIRED;
+            res.headers.append(
+                "proxy-authenticate",
+                HeaderValue::from_static("Basic realm=\"h2x\""),
+            );
+            let _ = res.send_headers();
+            return;
+        }
+        let Some(authority) = req.uri.authority().map(|a| a.to_string()) else {
+            res.status = StatusCode::BAD_REQUEST;
+            let _ = res.send_headers();
+            return;
+        };
+        let mut upstream = match TcpStream::connect(&authority).await {
+            Ok(stream) => stream,
+            Err(_) => {
+                res.status = StatusCode::BAD_GATEWAY;
+                let _ = res.send_headers();
+                return;
+            }
+        };
+        println!("[{}] CONNECT {authority}", self.addr);
+        let Ok(responder) = res.send_stream() else {
+            return;
+        };
+        let mut tunnel = Tunnel::new(req.body, responder);
+        let _ = copy_bidirectional(&mut tunnel, &mut upstream).await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+    let server = Server::bind("127.0.0.1:4433", conf).await?;
+
+    println!("Proxy listening on: https://{}", server.local_addr()?);
+
+    loop {
+        if let Ok((conn, addr)) = server.accept().await {
+            conn.incoming(Proxy { addr });
+        }
+    }
+}
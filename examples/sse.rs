@@ -0,0 +1,60 @@
+use h2x::{http::Method, *};
+use std::{io, time::Duration};
+
+#[derive(Clone)]
+struct Service;
+
+impl Incoming for Service {
+    async fn stream(self, req: Request, res: Response) {
+        let _ = match (&req.method, req.uri.path()) {
+            (&Method::GET, "/") => res.html(INDEX).await,
+            (&Method::GET, "/events") => events(req, res).await,
+            _ => res.error(http::StatusCode::NOT_FOUND, "Not Found").await,
+        };
+    }
+}
+
+async fn events(req: Request, res: Response) -> h2x::Result<()> {
+    let mut count: u64 = req
+        .last_event_id()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_default();
+
+    let mut sse = res.sse()?.keep_alive(Duration::from_secs(15));
+    loop {
+        sse.wait(tokio::time::sleep(Duration::from_secs(1))).await?;
+        count += 1;
+        let event = Event::new()
+            .event("tick")
+            .id(&count.to_string())
+            .data(&format!("tick #{count}"));
+
+        sse.send(&event).await?;
+    }
+}
+
+const INDEX: &str = r#"<!DOCTYPE html>
+<ul id="events"></ul>
+<script>
+  const source = new EventSource("/events");
+  source.addEventListener("tick", (e) => {
+    const li = document.createElement("li");
+    li.textContent = e.data;
+    document.getElementById("events").appendChild(li);
+  });
+</script>
+"#;
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+    let server = Server::bind("127.0.0.1:4433", conf).await?;
+
+    println!("Goto: https://{}", server.local_addr()?);
+
+    loop {
+        if let Ok((conn, _)) = server.accept().await {
+            conn.incoming(Service);
+        }
+    }
+}
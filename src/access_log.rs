@@ -0,0 +1,381 @@
+use super::*;
+use crate::stats::StreamStats;
+use http::header;
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    time::Instant,
+};
+
+/// Number of log lines buffered for the sink, lines are dropped once it is full.
+const QUEUE_SIZE: usize = 4096;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format of the lines written by [AccessLog].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Common Log Format: `peer - - [time] "method path HTTP/2.0" status bytes`
+    Common,
+    /// Combined Log Format, the Common Log Format followed by `"referer" "user-agent"`.
+    #[default]
+    Combined,
+    /// One JSON object per line, with the `time`, `peer`, `method`, `authority`, `path`, `status`,
+    /// `bytes`, `duration_ms`, `user_agent` and `stream_id` fields.
+    Json,
+}
+
+/// A service that logs every stream handled by the `inner` service.
+///
+/// Lines are sent to a background task writing them to the sink, so handlers never wait for it.
+/// If the sink can't keep up, lines are dropped.
+///
+/// A line is written once the handler returns, or is cancelled (see [Builder::handler_timeout]).
+/// The status is `-` (`null` in JSON) if no response was sent.
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// #[derive(Clone)]
+/// struct App;
+///
+/// impl Incoming for App {
+///     async fn stream(self, _: Request, res: Response) {
+///         let _ = res.text("Hello, World").await;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> std::io::Result<()> {
+///     let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+///     let server = Server::bind("127.0.0.1:4433", conf).await?;
+///     let app = AccessLog::new(App, tokio::io::stdout()).format(LogFormat::Json);
+///     loop {
+///         if let Ok((conn, _)) = server.accept().await {
+///             conn.incoming(app.clone());
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AccessLog<S> {
+    inner: S,
+    format: LogFormat,
+    sender: mpsc::Sender<String>,
+}
+
+impl<S> AccessLog<S> {
+    /// Wraps the `inner` service, writing [LogFormat::Combined] lines to `sink`.
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a Tokio runtime, as it spawns the task writing to the sink.
+    pub fn new(inner: S, sink: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(write_lines(receiver, sink));
+        Self {
+            inner,
+            format: LogFormat::default(),
+            sender,
+        }
+    }
+
+    /// Sets the format of the log lines. Default: [LogFormat::Combined]
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl<S: Incoming> Incoming for AccessLog<S> {
+    async fn stream(self, req: Request, res: Response) {
+        let _entry = Entry {
+            format: self.format,
+            sender: self.sender,
+            time: SystemTime::now(),
+            started: Instant::now(),
+            peer: req.peer_addr().map(|addr| addr.to_string()),
+            method: req.method.to_string(),
+            authority: req.uri.authority().map(|authority| authority.to_string()),
+            path: req
+                .uri
+                .path_and_query()
+                .map_or("/", |p| p.as_str())
+                .to_owned(),
+            referer: header_value(&req, header::REFERER),
+            user_agent: header_value(&req, header::USER_AGENT),
+            stream_id: u32::from(res.stream_id()),
+            stats: Arc::clone(&res.stats),
+        };
+        self.inner.stream(req, res).await;
+    }
+
+    #[inline]
+    fn on_connect(self, info: ConnInfo) -> impl Future<Output = Result<(), h2::Reason>> + Send {
+        self.inner.on_connect(info)
+    }
+
+    #[inline]
+    fn on_stream_reset(&self, stream_id: u32, reason: h2::Reason) {
+        self.inner.on_stream_reset(stream_id, reason);
+    }
+
+    #[inline]
+    fn on_goaway(&self, reason: h2::Reason, origin: Origin) {
+        self.inner.on_goaway(reason, origin);
+    }
+
+    #[inline]
+    fn on_settings(&self, settings: &PeerSettings) {
+        self.inner.on_settings(settings);
+    }
+
+    #[inline]
+    fn close(self) -> impl Future<Output = ()> + Send {
+        self.inner.close()
+    }
+
+    #[inline]
+    fn disconnect(self, result: &Result<(), ConnError>) -> impl Future<Output = ()> + Send {
+        self.inner.disconnect(result)
+    }
+}
+
+/// A stream being logged, the line is sent when it is dropped.
+struct Entry {
+    format: LogFormat,
+    sender: mpsc::Sender<String>,
+    time: SystemTime,
+    started: Instant,
+    peer: Option<String>,
+    method: String,
+    authority: Option<String>,
+    path: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    stream_id: u32,
+    stats: Arc<StreamStats>,
+}
+
+impl Entry {
+    fn line(&self) -> String {
+        let status = self.stats.status();
+        let bytes = self.stats.bytes_sent();
+        let mut line = String::with_capacity(128);
+        if self.format == LogFormat::Json {
+            line.push_str("{\"time\":");
+            push_json(&mut line, Some(&rfc3339(self.time)));
+            line.push_str(",\"peer\":");
+            push_json(&mut line, self.peer.as_deref());
+            line.push_str(",\"method\":");
+            push_json(&mut line, Some(&self.method));
+            line.push_str(",\"authority\":");
+            push_json(&mut line, self.authority.as_deref());
+            line.push_str(",\"path\":");
+            push_json(&mut line, Some(&self.path));
+            match status {
+                Some(status) => _ = write!(line, ",\"status\":{status}"),
+                None => line.push_str(",\"status\":null"),
+            }
+            let duration_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+            let _ = write!(line, ",\"bytes\":{bytes},\"duration_ms\":{duration_ms:.3}");
+            line.push_str(",\"user_agent\":");
+            push_json(&mut line, self.user_agent.as_deref());
+            let _ = write!(line, ",\"stream_id\":{}}}", self.stream_id);
+        } else {
+            let _ = write!(
+                line,
+                "{} - - [{}] \"{} {} HTTP/2.0\" ",
+                self.peer.as_deref().unwrap_or("-"),
+                clf_time(self.time),
+                self.method,
+                self.path,
+            );
+            match status {
+                Some(status) => _ = write!(line, "{status} "),
+                None => line.push_str("- "),
+            }
+            match bytes {
+                0 => line.push('-'),
+                bytes => _ = write!(line, "{bytes}"),
+            }
+            if self.format == LogFormat::Combined {
+                line.push(' ');
+                push_quoted(&mut line, self.referer.as_deref());
+                line.push(' ');
+                push_quoted(&mut line, self.user_agent.as_deref());
+            }
+        }
+        line.push('\n');
+        line
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let _ = self.sender.try_send(self.line());
+    }
+}
+
+async fn write_lines(
+    mut receiver: mpsc::Receiver<String>,
+    mut sink: impl AsyncWrite + Send + Unpin,
+) {
+    while let Some(line) = receiver.recv().await {
+        if sink.write_all(line.as_bytes()).await.is_err() {
+            return;
+        }
+        // Flush once the queued lines are written.
+        while let Ok(line) = receiver.try_recv() {
+            if sink.write_all(line.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+        if sink.flush().await.is_err() {
+            return;
+        }
+    }
+}
+
+fn header_value(req: &Request, name: header::HeaderName) -> Option<String> {
+    let value = req.headers.get(name)?.to_str().ok()?;
+    Some(value.to_owned())
+}
+
+/// Writes a quoted string of the Combined Log Format, `"-"` if it is missing.
+fn push_quoted(line: &mut String, value: Option<&str>) {
+    line.push('"');
+    match value {
+        Some(value) => {
+            for ch in value.chars() {
+                match ch {
+                    '"' | '\\' => {
+                        line.push('\\');
+                        line.push(ch);
+                    }
+                    _ => line.push(ch),
+                }
+            }
+        }
+        None => line.push('-'),
+    }
+    line.push('"');
+}
+
+/// Writes a JSON string, or `null` if it is missing.
+fn push_json(line: &mut String, value: Option<&str>) {
+    let Some(value) = value else {
+        line.push_str("null");
+        return;
+    };
+    line.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            ch if ch < ' ' => _ = write!(line, "\\u{:04x}", ch as u32),
+            ch => line.push(ch),
+        }
+    }
+    line.push('"');
+}
+
+/// Splits a time into UTC `(year, month, day, hours, minutes, seconds)`.
+fn utc(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+    // Civil from days, see <http://howardhinnant.github.io/date_algorithms.html>
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Formats a time as `10/Oct/2000:13:55:36 +0000`.
+fn clf_time(time: SystemTime) -> String {
+    let (year, month, day, h, m, s) = utc(time);
+    let month = MONTHS[month as usize - 1];
+    format!("{day:02}/{month}/{year}:{h:02}:{m:02}:{s:02} +0000")
+}
+
+/// Formats a time as `2000-10-10T13:55:36Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, h, m, s) = utc(time);
+    format!("{year}-{month:02}-{day:02}T{h:02}:{m:02}:{s:02}Z")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(utc(at(0)), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(clf_time(at(0)), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(rfc3339(at(0)), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn dates() {
+        // The time of the Common Log Format example, in UTC.
+        assert_eq!(clf_time(at(971186136)), "10/Oct/2000:13:55:36 +0000");
+        assert_eq!(rfc3339(at(971186136)), "2000-10-10T13:55:36Z");
+        assert_eq!(rfc3339(at(1445412480)), "2015-10-21T07:28:00Z");
+        assert_eq!(rfc3339(at(4102444799)), "2099-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn leap_years() {
+        assert_eq!(rfc3339(at(951782400)), "2000-02-29T00:00:00Z");
+        assert_eq!(rfc3339(at(951868800)), "2000-03-01T00:00:00Z");
+        assert_eq!(rfc3339(at(1709164800)), "2024-02-29T00:00:00Z");
+        assert_eq!(rfc3339(at(1735689599)), "2024-12-31T23:59:59Z");
+        assert_eq!(rfc3339(at(4107542400)), "2100-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn before_epoch_is_clamped() {
+        assert_eq!(
+            rfc3339(UNIX_EPOCH - Duration::from_secs(1)),
+            "1970-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let mut line = String::new();
+        push_quoted(&mut line, Some("a \"b\" \\c"));
+        push_quoted(&mut line, None);
+        assert_eq!(line, r#""a \"b\" \\c""-""#);
+
+        let mut line = String::new();
+        push_json(&mut line, Some("a\"\\\n\t\u{1}é"));
+        push_json(&mut line, None);
+        assert_eq!(line, r#""a\"\\\n\t\u0001é"null"#);
+    }
+}
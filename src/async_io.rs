@@ -0,0 +1,141 @@
+use super::*;
+use std::{io, pin::Pin, task::ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// An [AsyncRead] adapter over the request body, created from [RecvStream::into_async_read]
+///
+/// Reaching the end of the request body results in EOF.
+/// Received data is released back to the flow control window as it is read.
+pub struct BodyReader {
+    recv: RecvStream,
+    buf: Bytes,
+}
+
+/// An [AsyncWrite] adapter over the response body, created from [Responder::into_async_write]
+///
+/// Each write waits for send capacity and sends at most the capacity granted by the peer,
+/// so data is never buffered beyond the flow control window.
+///
+/// Calling `shutdown` ends the response stream. If the writer is dropped without shutting down,
+/// the stream is reset.
+pub struct BodyWriter {
+    send: Responder,
+    is_shutdown: bool,
+}
+
+impl RecvStream {
+    /// Converts the request body into an [AsyncRead] reader.
+    #[inline]
+    pub fn into_async_read(self) -> BodyReader {
+        BodyReader {
+            recv: self,
+            buf: Bytes::new(),
+        }
+    }
+}
+
+impl Responder {
+    /// Converts the response body sender into an [AsyncWrite] writer.
+    #[inline]
+    pub fn into_async_write(self) -> BodyWriter {
+        BodyWriter {
+            send: self,
+            is_shutdown: false,
+        }
+    }
+}
+
+impl BodyReader {
+    /// Returns a reference to the underlying [RecvStream].
+    #[inline]
+    pub fn get_ref(&self) -> &RecvStream {
+        &self.recv
+    }
+
+    /// Returns a mutable reference to the underlying [RecvStream].
+    ///
+    /// It can be used to retrieve the trailers after reaching EOF.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut RecvStream {
+        &mut self.recv
+    }
+
+    /// Consumes the reader, returning the underlying [RecvStream].
+    ///
+    /// Any data that was received but not yet read is lost.
+    #[inline]
+    pub fn into_inner(self) -> RecvStream {
+        self.recv
+    }
+}
+
+impl BodyWriter {
+    /// Returns a reference to the underlying [Responder].
+    #[inline]
+    pub fn get_ref(&self) -> &Responder {
+        &self.send
+    }
+
+    /// Returns a mutable reference to the underlying [Responder].
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut Responder {
+        &mut self.send
+    }
+
+    /// Consumes the writer, returning the underlying [Responder].
+    #[inline]
+    pub fn into_inner(self) -> Responder {
+        self.send
+    }
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.buf.is_empty() {
+            match ready!(this.recv.poll_data(cx)) {
+                Some(Ok(data)) => this.buf = data,
+                Some(Err(err)) => return Poll::Ready(Err(io_err(err))),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let len = buf.remaining().min(this.buf.len());
+        buf.put_slice(&this.buf.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for BodyWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let nbytes = ready!(this.send.poll_reserve(cx, buf.len())).map_err(io_err)?;
+        let data = Bytes::copy_from_slice(&buf[..nbytes]);
+        let sent = this.send.send_data(data, false).map(|_| nbytes);
+        Poll::Ready(sent.map_err(io_err))
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.is_shutdown {
+            this.is_shutdown = true;
+            this.send.send_data(Bytes::new(), true).map_err(io_err)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,202 @@
+use super::*;
+#[cfg(any(feature = "gzip", feature = "br", feature = "zstd"))]
+use crate::compression::Decoding;
+use bytes::BytesMut;
+use http::{header, HeaderMap, StatusCode};
+use std::fmt;
+
+/// Maximum request body size (in bytes) accepted by the body extractors: `2 MiB`
+pub const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// A request body that has been read to completion, created from [RecvStream::collect]
+#[derive(Debug, Default, Clone)]
+pub struct Collected {
+    /// The request body data.
+    pub data: Bytes,
+    /// Optional trailers sent after the body.
+    pub trailers: Option<HeaderMap>,
+}
+
+/// An error that can occur while reading a request body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BodyError {
+    /// The request body is larger than the allowed `limit` (in bytes).
+    TooLarge {
+        /// Maximum allowed body size.
+        limit: usize,
+    },
+    /// The request `content-type` does not match the `expected` media type.
+    UnsupportedMediaType {
+        /// The media type that was expected.
+        expected: &'static str,
+    },
+    /// The request `content-encoding` is not supported.
+    UnsupportedEncoding,
+    /// The client did not send the next chunk of the request body in time, see [Builder::body_read_timeout].
+    Timeout,
+    /// The request data could not be parsed.
+    Invalid(BoxErr),
+    /// An HTTP/2 error occurred while receiving the request body.
+    H2(h2::Error),
+}
+
+impl BodyError {
+    /// Returns the HTTP status code that best describes this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            BodyError::UnsupportedMediaType { .. } | BodyError::UnsupportedEncoding => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            BodyError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            BodyError::Invalid(_) | BodyError::H2(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl RecvStream {
+    /// Converts an error of this stream into a [BodyError].
+    pub(crate) fn body_err(&self, err: h2::Error) -> BodyError {
+        if self.timed_out {
+            BodyError::Timeout
+        } else {
+            BodyError::H2(err)
+        }
+    }
+
+    /// Reads the entire body and trailers into memory.
+    ///
+    /// Fails with [BodyError::TooLarge] as soon as the received data exceeds `limit` bytes,
+    /// without buffering the rest of the body.
+    pub async fn collect(&mut self, limit: usize) -> Result<Collected, BodyError> {
+        let mut len = 0;
+        let mut first = Bytes::new();
+        let mut rest = BytesMut::new();
+        while let Some(data) = self.data().await {
+            let data = data.map_err(|err| self.body_err(err))?;
+            len += data.len();
+            if len > limit {
+                return Err(BodyError::TooLarge { limit });
+            }
+            // Avoid copying when the whole body arrives in a single frame.
+            if first.is_empty() {
+                first = data;
+            } else {
+                if rest.is_empty() {
+                    rest.extend_from_slice(&first);
+                }
+                rest.extend_from_slice(&data);
+            }
+        }
+        let data = if rest.is_empty() {
+            first
+        } else {
+            rest.freeze()
+        };
+        let trailers = self.trailers().await.map_err(|err| self.body_err(err))?;
+        Ok(Collected { data, trailers })
+    }
+}
+
+/// Content codings listed in the `content-encoding` header, excluding `identity`.
+pub(crate) fn content_codings(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(header::CONTENT_ENCODING)
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or(",").split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.eq_ignore_ascii_case("identity"))
+}
+
+impl Request {
+    /// Reads the entire request body and trailers into memory.
+    ///
+    /// Unlike [RecvStream::collect], a request that declares a `content-length`
+    /// larger than `limit` is rejected early, before reading any data.
+    ///
+    /// With the `gzip`, `br` or `zstd` features enabled, a compressed body is decompressed
+    /// according to the `content-encoding` header, and `limit` applies to the decompressed size.
+    /// See [DecompressStream]
+    ///
+    /// Without these features, a body with any `content-encoding` other than `identity`
+    /// is rejected with [BodyError::UnsupportedEncoding].
+    pub async fn collect(&mut self, limit: usize) -> Result<Collected, BodyError> {
+        let content_length = self
+            .head
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+
+        if content_length.is_some_and(|len| len > limit as u64) {
+            return Err(BodyError::TooLarge { limit });
+        }
+        #[cfg(any(feature = "gzip", feature = "br", feature = "zstd"))]
+        if self.head.headers.contains_key(header::CONTENT_ENCODING) {
+            let mut decoder = Decoding::new(&self.head.headers, limit)?;
+            return decoder.collect(&mut self.body).await;
+        }
+        #[cfg(not(any(feature = "gzip", feature = "br", feature = "zstd")))]
+        if content_codings(&self.head.headers).next().is_some() {
+            return Err(BodyError::UnsupportedEncoding);
+        }
+        self.body.collect(limit).await
+    }
+}
+
+impl Response {
+    /// Responds to the client with the status code and message of the given [BodyError].
+    ///
+    /// If the error is caused by the stream itself (for example, the client reset the stream),
+    /// the stream is reset instead.
+    ///
+    /// Once the request is dropped, the remaining request body is discarded and the
+    /// stream is closed with `RST_STREAM(NO_ERROR)`, so the client stops uploading.
+    pub async fn reject(mut self, err: &BodyError) -> Result<()> {
+        if let BodyError::H2(err) = err {
+            self.sender
+                .send_reset(err.reason().unwrap_or(h2::Reason::CANCEL));
+            return Ok(());
+        }
+        #[cfg(any(feature = "gzip", feature = "br", feature = "zstd"))]
+        if let BodyError::UnsupportedEncoding = err {
+            self.headers
+                .insert(header::ACCEPT_ENCODING, Encoding::accepted());
+        }
+        self.error(err.status(), err.to_string()).await
+    }
+}
+
+impl From<h2::Error> for BodyError {
+    #[inline]
+    fn from(err: h2::Error) -> Self {
+        BodyError::H2(err)
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::TooLarge { limit } => {
+                write!(f, "request body exceeds the limit of {limit} bytes")
+            }
+            BodyError::UnsupportedMediaType { expected } => {
+                write!(f, "unsupported media type, expected `{expected}`")
+            }
+            BodyError::UnsupportedEncoding => f.write_str("unsupported content encoding"),
+            BodyError::Timeout => f.write_str("timed out reading the request body"),
+            BodyError::Invalid(err) => write!(f, "invalid request: {err}"),
+            BodyError::H2(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BodyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BodyError::Invalid(err) => Some(&**err),
+            BodyError::H2(err) => Some(err),
+            _ => None,
+        }
+    }
+}
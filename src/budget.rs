@@ -0,0 +1,98 @@
+use super::*;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Limits the bytes buffered by `write_unbound` on a connection, see [Builder::max_buffered_per_conn].
+#[derive(Debug)]
+pub(crate) struct ConnBudget {
+    per_stream: usize,
+    per_conn: usize,
+    buffered: AtomicUsize,
+}
+
+/// The share of a [ConnBudget] used by a single stream.
+#[derive(Debug)]
+pub(crate) struct StreamBudget {
+    conn: Arc<ConnBudget>,
+    buffered: Arc<AtomicUsize>,
+}
+
+/// Buffered data, the budget is released when h2 drops it after writing it to the connection.
+struct Tracked {
+    data: Bytes,
+    conn: Arc<ConnBudget>,
+    stream: Arc<AtomicUsize>,
+}
+
+impl ConnBudget {
+    pub(crate) fn new(per_stream: Option<usize>, per_conn: Option<usize>) -> Option<Arc<Self>> {
+        if per_stream.is_none() && per_conn.is_none() {
+            return None;
+        }
+        Some(Arc::new(Self {
+            per_stream: per_stream.unwrap_or(usize::MAX),
+            per_conn: per_conn.unwrap_or(usize::MAX),
+            buffered: AtomicUsize::new(0),
+        }))
+    }
+
+    pub(crate) fn stream(self: &Arc<Self>) -> StreamBudget {
+        StreamBudget {
+            conn: Arc::clone(self),
+            buffered: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl StreamBudget {
+    /// Charges `bytes` to the stream and connection budget.
+    ///
+    /// Fails with `ENHANCE_YOUR_CALM` if it would exceed either limit.
+    pub(crate) fn track(&self, bytes: Bytes) -> Result<Bytes> {
+        let len = bytes.len();
+        if len == 0 {
+            return Ok(bytes);
+        }
+        if !acquire(&self.buffered, len, self.conn.per_stream) {
+            return Err(over_budget());
+        }
+        if !acquire(&self.conn.buffered, len, self.conn.per_conn) {
+            self.buffered.fetch_sub(len, Ordering::AcqRel);
+            return Err(over_budget());
+        }
+        Ok(Bytes::from_owner(Tracked {
+            data: bytes,
+            conn: Arc::clone(&self.conn),
+            stream: Arc::clone(&self.buffered),
+        }))
+    }
+}
+
+fn acquire(buffered: &AtomicUsize, len: usize, limit: usize) -> bool {
+    buffered
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            used.checked_add(len).filter(|&total| total <= limit)
+        })
+        .is_ok()
+}
+
+fn over_budget() -> h2::Error {
+    h2::Error::from(h2::Reason::ENHANCE_YOUR_CALM)
+}
+
+impl AsRef<[u8]> for Tracked {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let len = self.data.len();
+        self.stream.fetch_sub(len, Ordering::AcqRel);
+        self.conn.buffered.fetch_sub(len, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+use bytes::BytesMut;
+
+/// Default target frame size of [BufResponder], which is the default `SETTINGS_MAX_FRAME_SIZE`.
+const DEFAULT_FRAME_SIZE: usize = 16 * 1024;
+
+/// A buffered response body writer, created from [Responder::into_buffered]
+///
+/// Small writes are coalesced into a single DATA frame of up to the target frame size,
+/// instead of sending a frame for each write. Call [BufResponder::flush] to send buffered
+/// data immediately, for example after each message of a streaming response.
+///
+/// Buffered data is discarded if the writer is dropped without calling [BufResponder::end].
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// async fn list(res: Response, items: &[&str]) -> Result<()> {
+///     let mut writer = res.send_stream()?.into_buffered();
+///     writer.write("[").await?;
+///     for (i, item) in items.iter().enumerate() {
+///         if i > 0 {
+///             writer.write(",").await?;
+///         }
+///         writer.write(format!("{item:?}")).await?;
+///     }
+///     writer.write("]").await?;
+///     writer.end().await
+/// }
+/// ```
+pub struct BufResponder {
+    responder: Responder,
+    buf: BytesMut,
+    frame_size: usize,
+}
+
+impl Responder {
+    /// Converts the response body sender into a [BufResponder] that coalesces small writes.
+    #[inline]
+    pub fn into_buffered(self) -> BufResponder {
+        BufResponder {
+            responder: self,
+            buf: BytesMut::new(),
+            frame_size: DEFAULT_FRAME_SIZE,
+        }
+    }
+}
+
+impl BufResponder {
+    /// Sets the target size of a DATA frame, buffered data is sent once it reaches this size. Default: 16 KiB
+    ///
+    /// Frames larger than the `SETTINGS_MAX_FRAME_SIZE` of the peer are split by h2.
+    pub fn frame_size(mut self, size: usize) -> Self {
+        self.frame_size = size.max(1);
+        self
+    }
+
+    /// Returns the number of buffered bytes, not yet sent.
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Buffers `bytes`, sending a frame each time the buffer reaches the target frame size.
+    ///
+    /// It waits until the flow control window allows sending the frame.
+    pub async fn write(&mut self, bytes: impl AsRef<[u8]>) -> Result<()> {
+        let mut bytes = bytes.as_ref();
+        while !bytes.is_empty() {
+            let len = (self.frame_size - self.buf.len()).min(bytes.len());
+            self.buf.extend_from_slice(&bytes[..len]);
+            bytes = &bytes[len..];
+            if self.buf.len() >= self.frame_size {
+                self.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends all buffered data to the remote peer.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let bytes = self.buf.split().freeze();
+        self.responder.write_bytes(bytes, false).await
+    }
+
+    /// Sends the remaining buffered data and ends the response body.
+    pub async fn end(mut self) -> Result<()> {
+        let bytes = self.buf.split().freeze();
+        self.responder.end_write(bytes).await
+    }
+
+    /// Gets a reference to the underlying [Responder].
+    #[inline]
+    pub fn get_ref(&self) -> &Responder {
+        &self.responder
+    }
+
+    /// Gets a mutable reference to the underlying [Responder].
+    ///
+    /// Writing directly to it bypasses (and precedes) the buffered data.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut Responder {
+        &mut self.responder
+    }
+
+    /// Sends all buffered data, and returns the underlying [Responder].
+    pub async fn into_inner(mut self) -> Result<Responder> {
+        self.flush().await?;
+        Ok(self.responder)
+    }
+}
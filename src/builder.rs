@@ -0,0 +1,441 @@
+use super::*;
+use crate::{
+    budget::ConnBudget,
+    flood::StreamTracker,
+    inspect::{Events, Inspect},
+    ip_limit::{IpLimiter, IpLimits},
+    keep_alive::{KeepAlive, DEFAULT_KEEP_ALIVE_TIMEOUT},
+    metrics::HandshakeFailure,
+    rate_limit::TokenBucket,
+    timeout::{self, IdleTimer, MaxAge, Timeouts},
+};
+use std::{ops, sync::Arc, time::Duration};
+use tokio::{
+    io::{self, AsyncRead, AsyncWrite},
+    net::ToSocketAddrs,
+    time::Instant,
+};
+use tokio_tls_listener::{rustls, TlsListener};
+
+/// Default [Builder::handshake_timeout] when [Builder::proxy_protocol] is enabled.
+const DEFAULT_PROXY_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configures HTTP/2 connections, created from [Server::builder].
+///
+/// It dereferences to [h2::server::Builder], so all the HTTP/2 protocol settings
+/// (window sizes, max concurrent streams, ...) can be set on it as well.
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+/// let server = Server::builder()
+///     .max_buffered_per_stream(256 * 1024)
+///     .max_buffered_per_conn(4 * 1024 * 1024)
+///     .bind("127.0.0.1:4433", conf)
+///     .await?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    h2: h2::server::Builder,
+    max_buffered_per_stream: Option<usize>,
+    max_buffered_per_conn: Option<usize>,
+    timeouts: Timeouts,
+    keep_alive_interval: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    stream_rate: Option<(u32, Duration)>,
+    reset_rate: Option<(u32, Duration)>,
+    settings_rate: Option<(u32, Duration)>,
+    max_concurrent_handlers: Option<usize>,
+    connection_events: bool,
+    ip_limits: IpLimits,
+    proxy_protocol: Option<ProxyProtocol>,
+    metrics: Option<Metrics>,
+}
+
+impl Builder {
+    /// Creates a new [Builder] with default configuration.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the bytes that `write_unbound` may buffer on a single stream. Default: unbounded
+    ///
+    /// Once the limit is reached, `write_unbound` fails with `ENHANCE_YOUR_CALM`
+    /// until the peer has read the buffered data.
+    /// Use [Responder::write] to wait for the peer instead.
+    pub fn max_buffered_per_stream(&mut self, max: usize) -> &mut Self {
+        self.max_buffered_per_stream = Some(max);
+        self
+    }
+
+    /// Limits the bytes that `write_unbound` may buffer across all streams of a connection. Default: unbounded
+    ///
+    /// See [Builder::max_buffered_per_stream].
+    pub fn max_buffered_per_conn(&mut self, max: usize) -> &mut Self {
+        self.max_buffered_per_conn = Some(max);
+        self
+    }
+
+    /// Limits the time to set up a connection. Default: none, `10` seconds with [Builder::proxy_protocol]
+    ///
+    /// For connections accepted by a [Server], it covers reading the PROXY protocol header,
+    /// the TLS handshake and the HTTP/2 handshake. As [Server::accept] waits for them,
+    /// a client that sends nothing delays other clients until it elapses.
+    /// Otherwise, it covers the HTTP/2 handshake: receiving the client connection preface
+    /// and exchanging the initial `SETTINGS` frames.
+    ///
+    /// Once the handshake is done, [Builder::request_header_timeout] and [Builder::body_read_timeout]
+    /// limit the time to receive a request.
+    /// The HTTP/2 handshake fails with `SETTINGS_TIMEOUT` when it elapses.
+    pub fn handshake_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.handshake = Some(timeout);
+        self
+    }
+
+    /// Limits the time to receive the headers of a request. Default: none
+    ///
+    /// It runs from the start of a `HEADERS` frame until the end of its header block,
+    /// including any `CONTINUATION` frames, so a client trickling them is bounded.
+    /// [h2] assembles the header block before a stream is accepted, and cannot reset a stream
+    /// whose headers are incomplete, so when it elapses the connection is closed with `GOAWAY(NO_ERROR)`.
+    pub fn request_header_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.header_read = Some(timeout);
+        self
+    }
+
+    /// Limits the time to wait for the next chunk of a request body. Default: none
+    ///
+    /// When it elapses, reading the body fails, and the body extractors return
+    /// [BodyError::Timeout], which is answered with `408 Request Timeout` by [Response::reject].
+    pub fn body_read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.body_read = Some(timeout);
+        self
+    }
+
+    /// Limits the time an [Incoming::stream] handler may run. Default: none
+    ///
+    /// When it elapses, the handler is cancelled. If the response headers were not sent yet,
+    /// `504 Gateway Timeout` is sent, otherwise the stream is reset with `CANCEL`.
+    pub fn handler_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.handler = Some(timeout);
+        self
+    }
+
+    /// Limits the time to wait for the peer to grant send capacity
+    /// while writing a response body. Default: none
+    ///
+    /// When it elapses, the stream is reset with `CANCEL` and the write fails.
+    pub fn write_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.write_idle = Some(timeout);
+        self
+    }
+
+    /// Closes the connection gracefully (with `GOAWAY`) once it had no open streams for `timeout`. Default: none
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.conn_idle = Some(timeout);
+        self
+    }
+
+    /// Recycles connections once they are `age` old, randomized by up to ±10%. Default: none
+    ///
+    /// The connection is closed gracefully: `GOAWAY` is sent so that the client opens a new connection,
+    /// and in-flight streams may finish within [Builder::max_connection_age_grace].
+    /// It lets load balancers rebalance long-lived connections.
+    pub fn max_connection_age(&mut self, age: Duration) -> &mut Self {
+        self.timeouts.max_age = Some(age);
+        self
+    }
+
+    /// Sets the time that in-flight streams have to finish once the connection reached
+    /// its maximum age, the connection is closed afterwards. Default: unlimited
+    ///
+    /// Only used if [Builder::max_connection_age] is set.
+    pub fn max_connection_age_grace(&mut self, grace: Duration) -> &mut Self {
+        self.timeouts.max_age_grace = Some(grace);
+        self
+    }
+
+    /// Sends a `PING` frame every `interval`, to detect dead peers and measure the round-trip time. Default: none
+    ///
+    /// The connection is closed if the peer does not acknowledge a ping within [Builder::keep_alive_timeout].
+    /// See [Conn::rtt]
+    pub fn keep_alive_interval(&mut self, interval: Duration) -> &mut Self {
+        self.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Sets the time to wait for a `PING` acknowledgement. Default: 20 seconds
+    ///
+    /// Only used if [Builder::keep_alive_interval] is set.
+    pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the rate of new streams to `count` per `period`, allowing bursts of up to `count` streams. Default: none
+    ///
+    /// When exceeded, the connection is closed with `GOAWAY(ENHANCE_YOUR_CALM)`.
+    pub fn max_stream_rate(&mut self, count: u32, period: Duration) -> &mut Self {
+        self.stream_rate = Some((count, period));
+        self
+    }
+
+    /// Limits the rate of `RST_STREAM` frames received from the peer to `count` per `period`. Default: none
+    ///
+    /// It protects against Rapid Reset (CVE-2023-44487) style floods, where streams are opened and
+    /// cancelled right away. When exceeded, the connection is closed with `GOAWAY(ENHANCE_YOUR_CALM)`.
+    ///
+    /// Resets sent by this server, such as refused streams, are not counted.
+    pub fn max_reset_rate(&mut self, count: u32, period: Duration) -> &mut Self {
+        self.reset_rate = Some((count, period));
+        self
+    }
+
+    /// Limits the rate of `SETTINGS` frames received from the peer to `count` per `period`. Default: none
+    ///
+    /// Each `SETTINGS` frame must be acknowledged, a peer sending them in a loop keeps the server busy.
+    /// When exceeded, the connection is closed with `GOAWAY(ENHANCE_YOUR_CALM)`.
+    /// Acknowledgements of the server's own `SETTINGS` are not counted.
+    pub fn max_settings_rate(&mut self, count: u32, period: Duration) -> &mut Self {
+        self.settings_rate = Some((count, period));
+        self
+    }
+
+    /// Limits the size of a request header block, as advertised by `SETTINGS_MAX_HEADER_LIST_SIZE`. Default: 16 MiB
+    ///
+    /// The size is the one of the decoded headers, counting 32 bytes of overhead per field.
+    /// [h2] (0.4.4 or later) also bounds the number of `CONTINUATION` frames of a header block from this size
+    /// and the max frame size, closing the connection with `GOAWAY(ENHANCE_YOUR_CALM)` when a peer sends more,
+    /// which protects against `CONTINUATION` floods.
+    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.h2.max_header_list_size(max);
+        self
+    }
+
+    /// Limits the response data that h2 buffers per stream before it is written to the connection. Default: ~400 KiB
+    ///
+    /// See [h2::server::Builder::max_send_buffer_size].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is larger than `u32::MAX`.
+    pub fn max_send_buffer_size(&mut self, max: usize) -> &mut Self {
+        self.h2.max_send_buffer_size(max);
+        self
+    }
+
+    /// Limits the number of [Incoming::stream] handlers running concurrently on a connection. Default: unlimited
+    ///
+    /// Streams beyond the limit are refused with `RST_STREAM(REFUSED_STREAM)`.
+    /// Consider also advertising a limit to the client with [h2::server::Builder::max_concurrent_streams].
+    pub fn max_concurrent_handlers(&mut self, max: usize) -> &mut Self {
+        self.max_concurrent_handlers = Some(max);
+        self
+    }
+
+    /// Reports the `SETTINGS`, `GOAWAY` and `RST_STREAM` frames of a connection to the
+    /// [Incoming::on_settings], [Incoming::on_goaway] and [Incoming::on_stream_reset] hooks. Default: `false`
+    ///
+    /// [h2] does not expose these frames, so they are parsed again from the received bytes, see [Inspect].
+    pub fn connection_events(&mut self, enabled: bool) -> &mut Self {
+        self.connection_events = enabled;
+        self
+    }
+
+    /// Limits the number of concurrent connections per client IP address (or subnet, see [Builder::ip_subnet]). Default: unlimited
+    ///
+    /// Connections beyond the limit are closed before the TLS handshake, and [Server::accept] fails with
+    /// [io::ErrorKind::ConnectionRefused].
+    ///
+    /// Only applies to connections accepted by a [Server] bound with this builder.
+    pub fn max_connections_per_ip(&mut self, max: usize) -> &mut Self {
+        self.ip_limits.max_conns = Some(max);
+        self
+    }
+
+    /// Limits the rate of requests per client IP address (or subnet, see [Builder::ip_subnet])
+    /// to `count` per `period`, across all its connections. Default: unlimited
+    ///
+    /// Requests beyond the limit are answered with `429 Too Many Requests` and a `retry-after` header,
+    /// without calling the handler.
+    ///
+    /// Only applies to connections accepted by a [Server] bound with this builder.
+    pub fn max_requests_per_ip(&mut self, count: u32, period: Duration) -> &mut Self {
+        self.ip_limits.request_rate = Some((count, period));
+        self
+    }
+
+    /// Groups clients by subnet for the per IP limits, with the given prefix lengths. Default: `32` and `128`
+    ///
+    /// For example, `ip_subnet(24, 64)` applies the limits per IPv4 `/24` and per IPv6 `/64` network.
+    pub fn ip_subnet(&mut self, ipv4_prefix: u8, ipv6_prefix: u8) -> &mut Self {
+        self.ip_limits.ipv4_prefix = ipv4_prefix;
+        self.ip_limits.ipv6_prefix = ipv6_prefix;
+        self
+    }
+
+    /// Parses a PROXY protocol header (version 1 or 2) sent by a load balancer, before the TLS handshake. Default: disabled
+    ///
+    /// [Server::accept] then returns the address of the original client, which the per IP limits apply to,
+    /// and the header is available from [Conn::proxy_header].
+    /// With [ProxyProtocol::Require], connections without a valid header are rejected.
+    ///
+    /// Only enable it if all connections come from a trusted load balancer, as clients could spoof their address otherwise.
+    ///
+    /// Unless [Builder::handshake_timeout] is set, connections must then be set up within `10` seconds,
+    /// so that a peer that sends nothing does not stall [Server::accept].
+    pub fn proxy_protocol(&mut self, mode: ProxyProtocol) -> &mut Self {
+        self.proxy_protocol = Some(mode);
+        self
+    }
+
+    #[inline]
+    pub(crate) fn proxy_protocol_mode(&self) -> Option<ProxyProtocol> {
+        self.proxy_protocol
+    }
+
+    /// Collects connection and stream metrics into `metrics`, see [Metrics]. Default: disabled
+    pub fn metrics(&mut self, metrics: &Metrics) -> &mut Self {
+        self.metrics = Some(metrics.clone());
+        self
+    }
+
+    pub(crate) fn on_accept(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_accept();
+        }
+    }
+
+    pub(crate) fn on_handshake_failure(&self, failure: HandshakeFailure) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_handshake_failure(failure);
+        }
+    }
+
+    /// Performs the HTTP/2 handshake on `io` with this configuration.
+    #[inline]
+    pub async fn handshake<IO>(&self, io: IO) -> Result<Conn<IO>>
+    where
+        IO: Unpin + AsyncRead + AsyncWrite,
+    {
+        self.handshake_until(io, self.handshake_deadline()).await
+    }
+
+    /// Returns when the handshake of a connection starting now must be done, see [Builder::handshake_timeout].
+    pub(crate) fn handshake_deadline(&self) -> Option<Instant> {
+        let timeout = match self.proxy_protocol {
+            Some(_) => self
+                .timeouts
+                .handshake
+                .or(Some(DEFAULT_PROXY_HANDSHAKE_TIMEOUT)),
+            None => self.timeouts.handshake,
+        };
+        timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    pub(crate) async fn handshake_until<IO>(
+        &self,
+        io: IO,
+        deadline: Option<Instant>,
+    ) -> Result<Conn<IO>>
+    where
+        IO: Unpin + AsyncRead + AsyncWrite,
+    {
+        let events = self.connection_events.then(Arc::<Events>::default);
+        let streams = Arc::new(StreamTracker::new(
+            self.reset_rate
+                .map(|(count, period)| TokenBucket::new(count, period)),
+            self.settings_rate
+                .map(|(count, period)| TokenBucket::new(count, period)),
+        ));
+        let io = Inspect::new(
+            io,
+            events.clone(),
+            Arc::clone(&streams),
+            self.timeouts.header_read,
+        );
+        let handshake = self.h2.handshake(io);
+        let mut inner = match timeout::until(deadline, handshake).await {
+            Some(Ok(inner)) => inner,
+            Some(Err(err)) => {
+                self.on_handshake_failure(HandshakeFailure::Http2);
+                return Err(err);
+            }
+            None => {
+                self.on_handshake_failure(HandshakeFailure::Timeout);
+                return Err(h2::Error::from(h2::Reason::SETTINGS_TIMEOUT));
+            }
+        };
+        let keep_alive = match (self.keep_alive_interval, inner.ping_pong()) {
+            (Some(interval), Some(ping_pong)) => Some(KeepAlive::new(
+                ping_pong,
+                interval,
+                self.keep_alive_timeout
+                    .unwrap_or(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            )),
+            _ => None,
+        };
+        Ok(Conn {
+            inner,
+            keep_alive,
+            idle: IdleTimer::new(self.timeouts.conn_idle),
+            max_age: self
+                .timeouts
+                .max_age
+                .map(|age| MaxAge::new(age, self.timeouts.max_age_grace)),
+            streams,
+            stream_rate: self
+                .stream_rate
+                .map(|(count, period)| TokenBucket::new(count, period)),
+            handlers: Arc::new(()),
+            max_handlers: self.max_concurrent_handlers,
+            is_flooded: false,
+            goaway_sent: None,
+            events,
+            client: None,
+            proxy_header: None,
+            peer_addr: None,
+            is_closed: false,
+            metrics: self.metrics.as_ref().map(Metrics::on_connect),
+            #[cfg(feature = "tracing")]
+            span: crate::trace::conn_span(),
+            budget: ConnBudget::new(self.max_buffered_per_stream, self.max_buffered_per_conn),
+            timeouts: self.timeouts,
+        })
+    }
+
+    /// Bind and listen for incoming connections on the specified address,
+    /// accepted connections use this configuration.
+    pub async fn bind(
+        &self,
+        addr: impl ToSocketAddrs,
+        conf: impl Into<Arc<rustls::ServerConfig>>,
+    ) -> io::Result<Server> {
+        Ok(Server {
+            listener: TlsListener::bind(addr, conf).await?,
+            builder: self.clone(),
+            ip_limiter: IpLimiter::new(self.ip_limits),
+        })
+    }
+}
+
+impl ops::Deref for Builder {
+    type Target = h2::server::Builder;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.h2
+    }
+}
+
+impl ops::DerefMut for Builder {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.h2
+    }
+}
@@ -0,0 +1,855 @@
+use super::*;
+use bytes::BytesMut;
+use http::{
+    header::{self, HeaderValue},
+    HeaderMap, StatusCode,
+};
+use std::io::{self, Write};
+
+/// Compressed output is sent once it reaches this size, unless flushed explicitly.
+const FLUSH_THRESHOLD: usize = 8 * 1024;
+
+/// A content coding supported for response compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `gzip` coding
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `br` (Brotli) coding
+    #[cfg(feature = "br")]
+    Brotli,
+    /// `zstd` (Zstandard) coding
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    /// Supported encodings, in order of server preference.
+    const ALL: &'static [Encoding] = &[
+        #[cfg(feature = "br")]
+        Encoding::Brotli,
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd,
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip,
+    ];
+
+    /// Returns the name of the content coding, as used in `content-encoding` header.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => "gzip",
+            #[cfg(feature = "br")]
+            Encoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Parses a content coding name, case-insensitively.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|encoding| name.eq_ignore_ascii_case(encoding.as_str()))
+    }
+
+    /// Selects the best encoding accepted by the client, based on the `accept-encoding` request header.
+    ///
+    /// Encodings with higher quality values are preferred. On a tie, the server preference
+    /// is used: `br`, `zstd`, then `gzip`. `*` only matches the encodings not listed
+    /// explicitly, so `gzip;q=0, *` excludes `gzip`.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+        let mut items = Vec::new();
+        for value in headers.get_all(header::ACCEPT_ENCODING) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for item in value.split(',') {
+                let mut params = item.split(';');
+                let name = params.next().unwrap_or_default().trim();
+                let quality = params
+                    .find_map(|param| {
+                        let (key, value) = param.split_once('=')?;
+                        key.trim().eq_ignore_ascii_case("q").then_some(value)
+                    })
+                    .map_or(Some(1000), parse_quality);
+                if let Some(quality) = quality {
+                    items.push((name, quality));
+                }
+            }
+        }
+        let listed: Vec<Encoding> = items
+            .iter()
+            .filter_map(|(name, _)| Self::from_name(name))
+            .collect();
+
+        let mut best: Option<(Encoding, u16)> = None;
+        for &(name, quality) in &items {
+            if quality == 0 {
+                continue;
+            }
+            let candidates: Vec<Encoding> = match name {
+                "*" => Self::ALL
+                    .iter()
+                    .copied()
+                    .filter(|encoding| !listed.contains(encoding))
+                    .collect(),
+                name => Self::from_name(name).into_iter().collect(),
+            };
+            for encoding in candidates {
+                let is_better = best.is_none_or(|(current, best_quality)| {
+                    quality > best_quality
+                        || (quality == best_quality && encoding.rank() < current.rank())
+                });
+                if is_better {
+                    best = Some((encoding, quality));
+                }
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Returns the supported encodings as an `accept-encoding` header value.
+    pub(crate) fn accepted() -> HeaderValue {
+        let names: Vec<_> = Self::ALL.iter().map(Encoding::as_str).collect();
+        HeaderValue::from_str(&names.join(", ")).unwrap()
+    }
+
+    fn rank(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|e| *e == self)
+            .unwrap_or(usize::MAX)
+    }
+}
+
+/// Parses a quality value (`0` to `1` with up to 3 decimal places) into `0..=1000`.
+fn parse_quality(value: &str) -> Option<u16> {
+    let value: f32 = value.trim().parse().ok()?;
+    (0.0..=1.0)
+        .contains(&value)
+        .then(|| (value * 1000.0).round() as u16)
+}
+
+/// Opt-in response compression.
+///
+/// Compression is skipped when:
+///
+/// - The client does not accept any of the enabled encodings.
+/// - The response already has a `content-encoding`.
+/// - The `content-type` is already compressed (images, audio, video, archives, fonts).
+/// - The body is smaller than [Compression::min_size] (for [Compression::write]).
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// async fn handler(req: Request, res: Response) -> Result<()> {
+///     let compression = Compression::new();
+///     let mut stream = compression.send_stream(&req, res)?;
+///     for i in 0..100 {
+///         stream.write(format!("line {i}\n")).await?;
+///     }
+///     stream.end().await
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self { min_size: 1024 }
+    }
+}
+
+impl Compression {
+    /// Creates a new [Compression] with default settings.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bodies smaller than `min_size` bytes are sent uncompressed. Default: `1024`
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Returns the encoding to use for the response, and sets the `vary` header.
+    fn select(&self, req: &Request, res: &mut Response) -> Option<Encoding> {
+        if res.headers.contains_key(header::CONTENT_ENCODING)
+            || matches!(
+                res.status,
+                StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+            )
+            || is_compressed_type(&res.headers)
+        {
+            return None;
+        }
+        res.headers
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        let encoding = Encoding::negotiate(&req.headers)?;
+        res.headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        res.headers.remove(header::CONTENT_LENGTH);
+        res.headers.remove(header::ACCEPT_RANGES);
+        // The compressed representation is no longer byte-for-byte identical.
+        if let Some(etag) = res.headers.get(header::ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                    res.headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+        Some(encoding)
+    }
+
+    /// Sends `bytes` as the response body, compressed if the client supports it.
+    pub async fn write(
+        &self,
+        req: &Request,
+        mut res: Response,
+        bytes: impl Into<Bytes>,
+    ) -> Result<()> {
+        let bytes = bytes.into();
+        if bytes.len() < self.min_size {
+            return res.write(bytes).await;
+        }
+        let Some(encoding) = self.select(req, &mut res) else {
+            return res.write(bytes).await;
+        };
+        let mut encoder = Encoder::new(Some(encoding));
+        encoder.write(&bytes).map_err(compression_err)?;
+        let compressed = encoder.finish().map_err(compression_err)?;
+
+        res.headers
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+        res.write(compressed).await
+    }
+
+    /// Sends the response headers, and returns a [CompressedResponder] that compresses
+    /// the response body if the client supports it.
+    pub fn send_stream(&self, req: &Request, mut res: Response) -> Result<CompressedResponder> {
+        let encoder = Encoder::new(self.select(req, &mut res));
+        Ok(CompressedResponder {
+            responder: res.send_stream()?,
+            encoder,
+        })
+    }
+}
+
+/// Streams a compressed response body, created from [Compression::send_stream]
+///
+/// Written data is buffered until it reaches a reasonable frame size, whether it is
+/// compressed or not. Call [CompressedResponder::flush] to send buffered data immediately,
+/// for example after each message of a streaming response.
+pub struct CompressedResponder {
+    responder: Responder,
+    encoder: Encoder,
+}
+
+impl CompressedResponder {
+    /// Returns the encoding applied to the response body, if any.
+    #[inline]
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoder.encoding
+    }
+
+    /// Compresses and writes a chunk of data.
+    pub async fn write(&mut self, bytes: impl AsRef<[u8]>) -> Result<()> {
+        self.encoder
+            .write(bytes.as_ref())
+            .map_err(compression_err)?;
+
+        if self.encoder.buffered() >= FLUSH_THRESHOLD {
+            let data = self.encoder.take();
+            self.responder.write(data).await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the compressor and sends all buffered data to the remote peer.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.encoder.flush().map_err(compression_err)?;
+        let data = self.encoder.take();
+        self.responder.write(data).await
+    }
+
+    /// Finishes compression and ends the response body.
+    pub async fn end(self) -> Result<()> {
+        let data = self.encoder.finish().map_err(compression_err)?;
+        self.responder.end_write(data).await
+    }
+}
+
+/// A request body wrapper that decompresses data according to the `content-encoding` request header.
+///
+/// The decompressed size is limited, which protects against decompression bombs.
+/// If the limit is exceeded, [BodyError::TooLarge] is returned.
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// async fn upload(req: Request, res: Response) -> Result<()> {
+///     let mut body = match DecompressStream::new(&req.head.headers, req.body, 16 * 1024 * 1024) {
+///         Ok(body) => body,
+///         Err(err) => return res.reject(&err).await,
+///     };
+///     while let Some(data) = body.data().await {
+///         match data {
+///             Ok(data) => println!("{} bytes", data.len()),
+///             Err(err) => return res.reject(&err).await,
+///         }
+///     }
+///     res.send_headers()
+/// }
+/// ```
+pub struct DecompressStream {
+    body: RecvStream,
+    decoding: Decoding,
+}
+
+impl DecompressStream {
+    /// Wraps the request body, decompressing it according to the `content-encoding` header.
+    ///
+    /// Fails with [BodyError::UnsupportedEncoding] if the encoding is not supported,
+    /// which should be answered with `415 Unsupported Media Type` (see [Response::reject]).
+    pub fn new(headers: &HeaderMap, body: RecvStream, limit: usize) -> Result<Self, BodyError> {
+        Ok(Self {
+            body,
+            decoding: Decoding::new(headers, limit)?,
+        })
+    }
+
+    /// Retrieve the next chunk of decompressed data.
+    #[inline]
+    pub async fn data(&mut self) -> Option<Result<Bytes, BodyError>> {
+        self.decoding.data(&mut self.body).await
+    }
+
+    /// Reads and decompresses the entire body and trailers into memory.
+    #[inline]
+    pub async fn collect(&mut self) -> Result<Collected, BodyError> {
+        self.decoding.collect(&mut self.body).await
+    }
+
+    /// Returns the encoding of the request body, or `None` if it is not compressed.
+    #[inline]
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.decoding.encoding
+    }
+
+    /// Consumes the wrapper, returning the underlying [RecvStream].
+    #[inline]
+    pub fn into_inner(self) -> RecvStream {
+        self.body
+    }
+}
+
+/// Decompression state of a request body.
+pub(crate) struct Decoding {
+    encoding: Option<Encoding>,
+    decoder: Option<Decoder>,
+    limit: usize,
+    len: usize,
+}
+
+impl Decoding {
+    pub(crate) fn new(headers: &HeaderMap, limit: usize) -> Result<Self, BodyError> {
+        let mut encodings = crate::body::content_codings(headers);
+
+        let encoding = match (encodings.next(), encodings.next()) {
+            (None, _) => None,
+            (Some(name), None) => {
+                let name = if name.eq_ignore_ascii_case("x-gzip") {
+                    "gzip"
+                } else {
+                    name
+                };
+                Some(Encoding::from_name(name).ok_or(BodyError::UnsupportedEncoding)?)
+            }
+            // Multiple encodings are not supported.
+            (Some(_), Some(_)) => return Err(BodyError::UnsupportedEncoding),
+        };
+        Ok(Self {
+            encoding,
+            decoder: encoding.map(|encoding| Decoder::new(encoding, limit)),
+            limit,
+            len: 0,
+        })
+    }
+
+    pub(crate) async fn data(&mut self, body: &mut RecvStream) -> Option<Result<Bytes, BodyError>> {
+        loop {
+            let Some(decoder) = &mut self.decoder else {
+                return match body.data().await? {
+                    Ok(data) => {
+                        self.len += data.len();
+                        if self.len > self.limit {
+                            return Some(Err(BodyError::TooLarge { limit: self.limit }));
+                        }
+                        Some(Ok(data))
+                    }
+                    Err(err) => Some(Err(body.body_err(err))),
+                };
+            };
+            match body.data().await {
+                Some(Ok(data)) => {
+                    if let Err(err) = decoder.write(&data) {
+                        return Some(Err(decoder.error(err)));
+                    }
+                    let data = decoder.take();
+                    if !data.is_empty() {
+                        return Some(Ok(data));
+                    }
+                }
+                Some(Err(err)) => return Some(Err(body.body_err(err))),
+                None => {
+                    let decoder = self.decoder.take()?;
+                    return match decoder.finish() {
+                        Ok(data) if data.is_empty() => None,
+                        Ok(data) => Some(Ok(data)),
+                        Err(err) => Some(Err(err)),
+                    };
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn collect(&mut self, body: &mut RecvStream) -> Result<Collected, BodyError> {
+        let mut buf = BytesMut::new();
+        while let Some(data) = self.data(body).await {
+            buf.extend_from_slice(&data?);
+        }
+        let trailers = body.trailers().await.map_err(|err| body.body_err(err))?;
+        Ok(Collected {
+            data: buf.freeze(),
+            trailers,
+        })
+    }
+}
+
+/// Collects decompressed output in memory, failing once the output exceeds the limit.
+///
+/// Decoders write their output in small chunks, so the memory used is bounded by the limit
+/// even when a small input expands to a huge output.
+struct Limited {
+    buf: Vec<u8>,
+    limit: usize,
+    len: usize,
+}
+
+impl Limited {
+    fn is_exceeded(&self) -> bool {
+        self.len > self.limit
+    }
+}
+
+impl Write for Limited {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.len += data.len();
+        if self.is_exceeded() {
+            return Err(io::Error::other("decompressed body exceeds the limit"));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzDecoder<Limited>),
+    #[cfg(feature = "br")]
+    Brotli(Box<brotli::DecompressorWriter<Limited>>),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdDecoder),
+}
+
+/// A zstd decoder that knows whether the last frame is complete,
+/// which `zstd::stream::write::Decoder` does not report.
+#[cfg(feature = "zstd")]
+struct ZstdDecoder {
+    raw: zstd::stream::raw::Decoder<'static>,
+    output: Limited,
+    chunk: Vec<u8>,
+    /// The hint returned by the last step, `0` once a frame is complete.
+    hint: usize,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdDecoder {
+    fn new(output: Limited) -> Self {
+        Self {
+            raw: zstd::stream::raw::Decoder::new().expect("zstd decoder"),
+            output,
+            chunk: vec![0; 4096],
+            hint: 1,
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+        let mut input = InBuffer::around(data);
+        loop {
+            let (pos, mut out) = (input.pos, OutBuffer::around(&mut self.chunk[..]));
+            let hint = self.raw.run(&mut input, &mut out)?;
+            let (len, is_full) = (out.pos(), out.pos() == out.capacity());
+            // A step without progress does not change the state of the frame.
+            if input.pos > pos || len > 0 {
+                self.hint = hint;
+            }
+            self.output.write_all(&self.chunk[..len])?;
+            // A full output buffer may leave decompressed bytes in the decoder.
+            if input.pos >= data.len() && !is_full {
+                return Ok(());
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write(&[])?;
+        if self.hint != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "incomplete zstd frame",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Decoder {
+    fn new(encoding: Encoding, limit: usize) -> Self {
+        let output = Limited {
+            buf: Vec::new(),
+            limit,
+            len: 0,
+        };
+        match encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Decoder::Gzip(flate2::write::GzDecoder::new(output)),
+            #[cfg(feature = "br")]
+            Encoding::Brotli => {
+                Decoder::Brotli(Box::new(brotli::DecompressorWriter::new(output, 4096)))
+            }
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Decoder::Zstd(ZstdDecoder::new(output)),
+        }
+    }
+
+    fn output(&mut self) -> &mut Limited {
+        match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.get_mut(),
+            #[cfg(feature = "br")]
+            Decoder::Brotli(decoder) => decoder.get_mut(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(decoder) => &mut decoder.output,
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.write_all(data),
+            #[cfg(feature = "br")]
+            Decoder::Brotli(decoder) => decoder.write_all(data),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(decoder) => decoder.write(data),
+        }
+    }
+
+    /// Maps a decoding error to [BodyError].
+    fn error(&mut self, err: io::Error) -> BodyError {
+        let output = self.output();
+        if output.is_exceeded() {
+            return BodyError::TooLarge {
+                limit: output.limit,
+            };
+        }
+        BodyError::Invalid(err.into())
+    }
+
+    /// Takes the decompressed bytes produced so far.
+    fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut self.output().buf))
+    }
+
+    fn finish(mut self) -> Result<Bytes, BodyError> {
+        let result = match &mut self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.try_finish(),
+            #[cfg(feature = "br")]
+            Decoder::Brotli(decoder) => decoder.close(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(decoder) => decoder.finish(),
+        };
+        match result {
+            Ok(()) => Ok(self.take()),
+            Err(err) => Err(self.error(err)),
+        }
+    }
+}
+
+fn compression_err(_: io::Error) -> h2::Error {
+    h2::Error::from(h2::Reason::INTERNAL_ERROR)
+}
+
+fn is_compressed_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    match essence.split_once('/') {
+        Some(("image", subtype)) => subtype != "svg+xml" && subtype != "bmp",
+        Some(("audio" | "video", _)) => true,
+        Some(("font", subtype)) => subtype == "woff" || subtype == "woff2",
+        Some(("application", subtype)) => matches!(
+            subtype,
+            "zip"
+                | "gzip"
+                | "x-gzip"
+                | "zstd"
+                | "x-bzip2"
+                | "x-xz"
+                | "x-7z-compressed"
+                | "x-rar-compressed"
+                | "vnd.rar"
+        ),
+        _ => false,
+    }
+}
+
+struct Encoder {
+    encoding: Option<Encoding>,
+    inner: EncoderInner,
+}
+
+enum EncoderInner {
+    Identity(Vec<u8>),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    #[cfg(feature = "br")]
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Option<Encoding>) -> Self {
+        let inner = match encoding {
+            None => EncoderInner::Identity(Vec::new()),
+            #[cfg(feature = "gzip")]
+            Some(Encoding::Gzip) => EncoderInner::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+            // Quality `4` is a good trade-off between speed and ratio for dynamic content.
+            #[cfg(feature = "br")]
+            Some(Encoding::Brotli) => EncoderInner::Brotli(Box::new(
+                brotli::CompressorWriter::new(Vec::new(), 4096, 4, 22),
+            )),
+            #[cfg(feature = "zstd")]
+            Some(Encoding::Zstd) => EncoderInner::Zstd(
+                zstd::stream::write::Encoder::new(Vec::new(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .expect("zstd encoder"),
+            ),
+        };
+        Self { encoding, inner }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.inner {
+            EncoderInner::Identity(buf) => buf.write_all(data),
+            #[cfg(feature = "gzip")]
+            EncoderInner::Gzip(encoder) => encoder.write_all(data),
+            #[cfg(feature = "br")]
+            EncoderInner::Brotli(encoder) => encoder.write_all(data),
+            #[cfg(feature = "zstd")]
+            EncoderInner::Zstd(encoder) => encoder.write_all(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            EncoderInner::Identity(_) => Ok(()),
+            #[cfg(feature = "gzip")]
+            EncoderInner::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "br")]
+            EncoderInner::Brotli(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            EncoderInner::Zstd(encoder) => encoder.flush(),
+        }
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        match &mut self.inner {
+            EncoderInner::Identity(buf) => buf,
+            #[cfg(feature = "gzip")]
+            EncoderInner::Gzip(encoder) => encoder.get_mut(),
+            #[cfg(feature = "br")]
+            EncoderInner::Brotli(encoder) => encoder.get_mut(),
+            #[cfg(feature = "zstd")]
+            EncoderInner::Zstd(encoder) => encoder.get_mut(),
+        }
+    }
+
+    /// Number of bytes waiting to be sent.
+    fn buffered(&mut self) -> usize {
+        self.output().len()
+    }
+
+    /// Takes the bytes produced so far.
+    fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(self.output()))
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let data = match self.inner {
+            EncoderInner::Identity(buf) => buf,
+            #[cfg(feature = "gzip")]
+            EncoderInner::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "br")]
+            EncoderInner::Brotli(encoder) => encoder.into_inner(),
+            #[cfg(feature = "zstd")]
+            EncoderInner::Zstd(encoder) => encoder.finish()?,
+        };
+        Ok(Bytes::from(data))
+    }
+}
+
+#[cfg(all(test, feature = "gzip", feature = "br", feature = "zstd"))]
+mod tests {
+    use super::*;
+
+    fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(accept_encoding).unwrap(),
+        );
+        Encoding::negotiate(&headers)
+    }
+
+    #[test]
+    fn prefers_highest_quality_then_server_order() {
+        assert_eq!(negotiate("gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip, zstd"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip;q=1, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn zero_quality_excludes() {
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0"), None);
+    }
+
+    #[test]
+    fn wildcard_matches_only_unlisted_encodings() {
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("br;q=0, zstd;q=0, *, gzip;q=0"), None);
+        assert_eq!(negotiate("*, br;q=0, zstd;q=0, gzip;q=0"), None);
+        assert_eq!(negotiate("br;q=0, *"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip;q=0.9, *;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.1, *;q=0.5"), Some(Encoding::Zstd));
+    }
+
+    #[test]
+    fn quality_parameter() {
+        assert_eq!(negotiate("GZIP;Q=0.5, br;q=0.4"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip; q=0.5, br; q=0.6"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=2"), None);
+        assert_eq!(negotiate("gzip;q=abc"), None);
+        assert_eq!(parse_quality("0.125"), Some(125));
+        assert_eq!(parse_quality("1"), Some(1000));
+    }
+
+    fn compress(encoding: Encoding, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(Some(encoding));
+        encoder.write(data).unwrap();
+        encoder.finish().unwrap().to_vec()
+    }
+
+    fn decompress(encoding: Encoding, data: &[u8], limit: usize) -> Result<Bytes, BodyError> {
+        let mut decoder = Decoder::new(encoding, limit);
+        let mut out = BytesMut::new();
+        for chunk in data.chunks(100) {
+            decoder.write(chunk).map_err(|err| decoder.error(err))?;
+            out.extend_from_slice(&decoder.take());
+        }
+        out.extend_from_slice(&decoder.finish()?);
+        Ok(out.freeze())
+    }
+
+    fn body() -> Vec<u8> {
+        (0..100_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn decompresses() {
+        let body = body();
+        for encoding in Encoding::ALL.iter().copied() {
+            let compressed = compress(encoding, &body);
+            let out = decompress(encoding, &compressed, usize::MAX).unwrap();
+            assert_eq!(out, body, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        let body = body();
+        for encoding in Encoding::ALL.iter().copied() {
+            let compressed = compress(encoding, &body);
+            for len in [compressed.len() - 1, compressed.len() / 2] {
+                let result = decompress(encoding, &compressed[..len], usize::MAX);
+                assert!(
+                    matches!(result, Err(BodyError::Invalid(_))),
+                    "{encoding:?} truncated to {len} bytes: {result:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn limits_decompressed_size() {
+        let body = vec![0; 1 << 20];
+        for encoding in Encoding::ALL.iter().copied() {
+            let compressed = compress(encoding, &body);
+            let result = decompress(encoding, &compressed, 1000);
+            assert!(
+                matches!(result, Err(BodyError::TooLarge { limit: 1000 })),
+                "{encoding:?}: {result:?}"
+            );
+        }
+    }
+}
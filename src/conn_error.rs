@@ -0,0 +1,77 @@
+use super::*;
+use std::{error::Error, fmt, io};
+
+/// Which side of the connection initiated its termination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// This server, for example on a protocol error of the peer or a rate limit (see [Builder::max_stream_rate]).
+    Local,
+    /// The peer.
+    Remote,
+}
+
+/// Why a connection ended abnormally, see [Incoming::disconnect] and [Conn::incoming].
+#[derive(Debug)]
+pub enum ConnError {
+    /// A `GOAWAY` frame with an error code was sent or received.
+    GoAway {
+        /// The error code of the `GOAWAY` frame.
+        reason: h2::Reason,
+        /// The side that sent the `GOAWAY` frame.
+        origin: Origin,
+    },
+    /// The peer did not acknowledge a `PING` in time, see [Builder::keep_alive_timeout].
+    KeepAliveTimeout,
+    /// Reading from or writing to the underlying transport failed.
+    Io(io::Error),
+}
+
+impl ConnError {
+    /// Returns the side that initiated the termination, `None` for I/O errors.
+    pub fn origin(&self) -> Option<Origin> {
+        match self {
+            ConnError::GoAway { origin, .. } => Some(*origin),
+            ConnError::KeepAliveTimeout => Some(Origin::Local),
+            ConnError::Io(_) => None,
+        }
+    }
+}
+
+impl From<h2::Error> for ConnError {
+    fn from(err: h2::Error) -> Self {
+        let origin = if err.is_remote() {
+            Origin::Remote
+        } else {
+            Origin::Local
+        };
+        match err.reason() {
+            Some(reason) => ConnError::GoAway { reason, origin },
+            None => ConnError::Io(
+                err.into_io()
+                    .unwrap_or_else(|| io::Error::other("unknown error")),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnError::GoAway { reason, origin } => match origin {
+                Origin::Local => write!(f, "connection closed with GOAWAY: {reason}"),
+                Origin::Remote => write!(f, "connection closed by peer with GOAWAY: {reason}"),
+            },
+            ConnError::KeepAliveTimeout => f.write_str("keep-alive timeout elapsed"),
+            ConnError::Io(err) => write!(f, "connection error: {err}"),
+        }
+    }
+}
+
+impl Error for ConnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConnError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use super::*;
+use serde::de::DeserializeOwned;
+
+impl Request {
+    /// Returns `true` if the essence of the request `content-type` (ignoring parameters)
+    /// matches the given predicate.
+    #[cfg(any(feature = "json", feature = "form"))]
+    fn content_type_is(&self, f: impl FnOnce(&str) -> bool) -> bool {
+        self.head
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or_default().trim())
+            .is_some_and(|essence| f(&essence.to_ascii_lowercase()))
+    }
+
+    /// Deserializes the request body as JSON.
+    ///
+    /// The `content-type` must be `application/json` (or `application/*+json`),
+    /// and the body must not exceed [DEFAULT_BODY_LIMIT].
+    ///
+    /// The error can be sent back to the client with [Response::reject].
+    #[cfg(feature = "json")]
+    pub async fn json<T: DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        let is_json = self.content_type_is(|essence| {
+            essence == "application/json"
+                || (essence.starts_with("application/") && essence.ends_with("+json"))
+        });
+        if !is_json {
+            return Err(BodyError::UnsupportedMediaType {
+                expected: "application/json",
+            });
+        }
+        let body = self.collect(DEFAULT_BODY_LIMIT).await?;
+        serde_json::from_slice(&body.data).map_err(|err| BodyError::Invalid(err.into()))
+    }
+
+    /// Deserializes the request body as `application/x-www-form-urlencoded` form data.
+    ///
+    /// The body must not exceed [DEFAULT_BODY_LIMIT].
+    ///
+    /// The error can be sent back to the client with [Response::reject].
+    #[cfg(feature = "form")]
+    pub async fn form<T: DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        if !self.content_type_is(|essence| essence == "application/x-www-form-urlencoded") {
+            return Err(BodyError::UnsupportedMediaType {
+                expected: "application/x-www-form-urlencoded",
+            });
+        }
+        let body = self.collect(DEFAULT_BODY_LIMIT).await?;
+        serde_urlencoded::from_bytes(&body.data).map_err(|err| BodyError::Invalid(err.into()))
+    }
+
+    /// Deserializes the query string of the request uri.
+    ///
+    /// A missing query string is treated as empty.
+    #[cfg(feature = "query")]
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T, BodyError> {
+        let query = self.head.uri.query().unwrap_or_default();
+        serde_urlencoded::from_str(query).map_err(|err| BodyError::Invalid(err.into()))
+    }
+}
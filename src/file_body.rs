@@ -0,0 +1,152 @@
+use super::*;
+use crate::range::{write_ranges, RangeBody};
+use bytes::BytesMut;
+use std::{
+    io::{self, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+/// Upper bound of a single read, even if the peer grants more capacity.
+const MAX_READ_SIZE: usize = 256 * 1024;
+
+/// A file used as response body.
+///
+/// The file is sent in chunks that match the send capacity granted by the peer's flow control
+/// window, so no more than the granted capacity is ever read into memory for a stream.
+///
+/// With the `mmap` feature, the file can be memory-mapped with [FileBody::mmap],
+/// in which case chunks are sent directly from the mapped memory without copying.
+pub struct FileBody {
+    source: Source,
+    len: u64,
+}
+
+enum Source {
+    File(File),
+    #[cfg(feature = "mmap")]
+    Mmap(Bytes),
+}
+
+impl FileBody {
+    /// Opens the file at `path` in read-only mode.
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_file(File::open(path).await?).await
+    }
+
+    /// Creates a [FileBody] from an open file.
+    pub async fn from_file(file: File) -> io::Result<Self> {
+        let len = file.metadata().await?.len();
+        Ok(Self::new(file, len))
+    }
+
+    pub(crate) fn new(file: File, len: u64) -> Self {
+        Self {
+            source: Source::File(file),
+            len,
+        }
+    }
+
+    /// Memory-maps the file at `path`.
+    ///
+    /// The file must not be modified or truncated while it is mapped,
+    /// otherwise the process may receive `SIGBUS` or observe inconsistent data.
+    #[cfg(feature = "mmap")]
+    pub fn mmap(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        if len == 0 {
+            // Mapping an empty file fails on some platforms.
+            return Ok(Self {
+                source: Source::Mmap(Bytes::new()),
+                len,
+            });
+        }
+        // SAFETY: The caller is responsible for not modifying the file while it is mapped, as documented above.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Self {
+            source: Source::Mmap(Bytes::from_owner(mmap)),
+            len,
+        })
+    }
+
+    /// Returns the size of the file in bytes.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the file is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sends the bytes of the file within `range`, without ending the response.
+    pub async fn write_to(&mut self, responder: &mut Responder, range: Range<u64>) -> Result<()> {
+        let range = range.start.min(self.len)..range.end.min(self.len);
+        let mut remaining = range.end.saturating_sub(range.start);
+        match &mut self.source {
+            Source::File(file) => {
+                file.seek(SeekFrom::Start(range.start))
+                    .await
+                    .map_err(file_err)?;
+
+                while remaining > 0 {
+                    let len = remaining.min(MAX_READ_SIZE as u64) as usize;
+                    let nbytes = poll_fn(|cx| responder.poll_reserve(cx, len)).await?;
+
+                    let mut buf = BytesMut::with_capacity(nbytes);
+                    let read = file.read_buf(&mut buf).await.map_err(file_err)?;
+                    if read == 0 {
+                        // The file was truncated.
+                        return Err(file_err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    remaining -= read as u64;
+                    responder.send_data(buf.freeze(), false)?;
+                }
+            }
+            #[cfg(feature = "mmap")]
+            Source::Mmap(bytes) => {
+                let mut offset = range.start as usize;
+                while remaining > 0 {
+                    let len = remaining.min(MAX_READ_SIZE as u64) as usize;
+                    let nbytes = poll_fn(|cx| responder.poll_reserve(cx, len)).await?;
+                    responder.send_data(bytes.slice(offset..offset + nbytes), false)?;
+                    offset += nbytes;
+                    remaining -= nbytes as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RangeBody for FileBody {
+    #[inline]
+    fn write_range(
+        &mut self,
+        responder: &mut Responder,
+        range: Range<u64>,
+    ) -> impl Future<Output = Result<()>> + Send {
+        self.write_to(responder, range)
+    }
+}
+
+impl Response {
+    /// Sends the file as response body, with `content-length` header and
+    /// byte-range support (see [Response::write_ranged]).
+    #[inline]
+    pub async fn write_file(self, req: &Request, body: FileBody) -> Result<()> {
+        let len = body.len();
+        write_ranges(self, req, body, len).await
+    }
+}
+
+fn file_err(_: io::Error) -> h2::Error {
+    h2::Error::from(h2::Reason::INTERNAL_ERROR)
+}
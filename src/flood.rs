@@ -0,0 +1,182 @@
+use super::*;
+use crate::rate_limit::TokenBucket;
+use h2::Reason;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::Waker,
+};
+
+/// Tracks the streams of a connection, each open stream holds an [ActiveStream].
+///
+/// `RST_STREAM` and `SETTINGS` frames received from the peer are counted against their rate limits,
+/// see [Builder::max_reset_rate] and [Builder::max_settings_rate].
+#[derive(Debug, Default)]
+pub(crate) struct StreamTracker {
+    resets: Option<Mutex<TokenBucket>>,
+    settings: Option<Mutex<TokenBucket>>,
+    is_flooded: AtomicBool,
+    /// Set once [Builder::request_header_timeout] elapsed.
+    header_timed_out: AtomicBool,
+    active: AtomicUsize,
+    /// Woken once the last active stream is dropped, see [Builder::idle_timeout].
+    idle: Mutex<Option<Waker>>,
+}
+
+impl StreamTracker {
+    pub(crate) fn new(resets: Option<TokenBucket>, settings: Option<TokenBucket>) -> Self {
+        Self {
+            resets: resets.map(Mutex::new),
+            settings: settings.map(Mutex::new),
+            is_flooded: AtomicBool::new(false),
+            header_timed_out: AtomicBool::new(false),
+            active: AtomicUsize::new(0),
+            idle: Mutex::new(None),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn on_header_timeout(&self) {
+        self.header_timed_out.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` once after the request header timeout elapsed.
+    #[inline]
+    pub(crate) fn take_header_timeout(&self) -> bool {
+        self.header_timed_out.swap(false, Ordering::AcqRel)
+    }
+
+    /// Returns the number of streams with a response in progress.
+    #[inline]
+    pub(crate) fn num_active(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Polls until no stream is active, the task is woken once the last one is dropped.
+    pub(crate) fn poll_idle(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut idle = self.idle.lock().unwrap();
+        if self.num_active() == 0 {
+            return Poll::Ready(());
+        }
+        match &mut *idle {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            waker => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+
+    /// Returns `true` if a rate limit of the connection was exceeded.
+    #[inline]
+    pub(crate) fn is_flooded(&self) -> bool {
+        self.is_flooded.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn flood(&self) {
+        self.is_flooded.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the `RST_STREAM` or `SETTINGS` frames received from the peer are limited.
+    #[inline]
+    pub(crate) fn limits_frames(&self) -> bool {
+        self.resets.is_some() || self.settings.is_some()
+    }
+
+    /// Counts a `RST_STREAM` frame received from the peer.
+    pub(crate) fn on_peer_reset(&self, reason: Reason) {
+        if reason == Reason::NO_ERROR {
+            return;
+        }
+        debug!(?reason, "stream reset by peer");
+        if let Some(resets) = &self.resets {
+            if !resets.lock().unwrap().try_acquire() {
+                self.flood();
+            }
+        }
+    }
+
+    /// Counts a `SETTINGS` frame received from the peer, acknowledgements excluded.
+    pub(crate) fn on_peer_settings(&self) {
+        if let Some(settings) = &self.settings {
+            if !settings.lock().unwrap().try_acquire() {
+                debug!("settings rate exceeded");
+                self.flood();
+            }
+        }
+    }
+}
+
+/// Counts a stream as active while it is alive, see [Conn::num_active_streams].
+#[derive(Debug)]
+pub(crate) struct ActiveStream(Arc<StreamTracker>);
+
+impl ActiveStream {
+    pub(crate) fn new(streams: &Arc<StreamTracker>) -> Self {
+        streams.active.fetch_add(1, Ordering::AcqRel);
+        Self(Arc::clone(streams))
+    }
+}
+
+impl Clone for ActiveStream {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::new(&self.0)
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        if self.0.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            if let Some(waker) = self.0.idle.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{task::Wake, time::Duration};
+
+    #[derive(Default)]
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn wakes_once_idle() {
+        let streams = Arc::new(StreamTracker::default());
+        let count = Arc::new(Count::default());
+        let waker = Waker::from(Arc::clone(&count));
+        let mut cx = Context::from_waker(&waker);
+        assert!(streams.poll_idle(&mut cx).is_ready());
+
+        let stream = ActiveStream::new(&streams);
+        let responder = stream.clone();
+        assert_eq!(streams.num_active(), 2);
+        assert!(streams.poll_idle(&mut cx).is_pending());
+        drop(stream);
+        assert_eq!(count.0.load(Ordering::Relaxed), 0);
+        drop(responder);
+        assert_eq!(count.0.load(Ordering::Relaxed), 1);
+        assert!(streams.poll_idle(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn limits_settings() {
+        let streams = StreamTracker::new(None, Some(TokenBucket::new(2, Duration::from_secs(60))));
+        assert!(streams.limits_frames());
+        streams.on_peer_settings();
+        streams.on_peer_settings();
+        assert!(!streams.is_flooded());
+        streams.on_peer_settings();
+        assert!(streams.is_flooded());
+    }
+}
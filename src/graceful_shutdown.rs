@@ -1,9 +1,12 @@
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    net::TcpStream, task,
+    net::TcpStream,
+    task,
 };
 use tokio_tls_listener::tokio_rustls::server::TlsStream;
 
+use crate::handler::PerConn;
+
 use super::*;
 use std::{
     future, io,
@@ -78,23 +81,37 @@ where
     IO: Unpin + AsyncRead + AsyncWrite + Send + 'static,
 {
     /// See [`Conn::incoming`]
-    pub fn incoming(mut self, _s: impl Incoming) -> task::JoinHandle<()> {
-        tokio::spawn(async move {
-            while let Some(Ok((req, res))) = self.inner.accept().await {
-                if self.is_closed.load(Ordering::Acquire) {
-                    self.inner.graceful_shutdown();
-                } else {
-                    let is_closed = Arc::clone(&self.is_closed);
-                    let state = _s.clone();
-                    tokio::spawn(async move {
-                        state.stream(req, res).await;
-                        drop(is_closed);
-                    });
+    pub fn incoming(mut self, _s: impl Incoming) -> task::JoinHandle<Result<(), ConnError>> {
+        #[cfg(feature = "tracing")]
+        let span = self.inner.span.clone();
+        let task = async move {
+            self.inner.connect(_s.clone()).await;
+            let result = loop {
+                match self.inner.next(_s.clone()).await {
+                    Some(Ok(_)) if self.is_closed.load(Ordering::Acquire) => {
+                        self.inner.go_away(None);
+                    }
+                    Some(Ok((req, res))) => {
+                        let is_closed = Arc::clone(&self.is_closed);
+                        self.inner.spawn(_s.clone(), req, res, is_closed);
+                    }
+                    Some(Err(err)) => break self.inner.close_reason(Some(err)),
+                    None => break self.inner.close_reason(None),
                 }
-            }
-            _s.close().await;
+            };
+            _s.disconnect(&result).await;
             drop(self.is_closed);
-        })
+            result
+        };
+        #[cfg(feature = "tracing")]
+        let task = tracing::Instrument::instrument(task, span);
+        tokio::spawn(task)
+    }
+
+    /// See [`Conn::serve`]
+    #[inline]
+    pub fn serve<H: Handler>(self, handler: Arc<H>) -> task::JoinHandle<Result<(), ConnError>> {
+        self.incoming(PerConn::new(handler))
     }
 }
 
@@ -0,0 +1,173 @@
+use super::*;
+use std::sync::{Arc, OnceLock};
+
+/// Serving connections with a state shared by the whole server, and a state per connection.
+///
+/// Unlike [Incoming], which is cloned for every stream, a handler is shared through an [Arc],
+/// and creates the state of each connection once, in [Handler::connect].
+/// Streams of the connection receive a reference to it.
+///
+/// As streams are handled concurrently, mutable data of a connection needs interior mutability,
+/// such as a [Mutex](std::sync::Mutex) or atomics.
+///
+/// See [Conn::serve].
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+/// use std::sync::{
+///     atomic::{AtomicU64, Ordering},
+///     Arc,
+/// };
+///
+/// #[derive(Default)]
+/// struct App {
+///     connections: AtomicU64,
+/// }
+///
+/// struct Client {
+///     id: u64,
+///     requests: AtomicU64,
+/// }
+///
+/// impl Handler for App {
+///     type State = Client;
+///
+///     async fn connect(&self, _: &ConnInfo) -> Result<Client, h2::Reason> {
+///         let id = self.connections.fetch_add(1, Ordering::Relaxed);
+///         Ok(Client { id, requests: AtomicU64::new(0) })
+///     }
+///
+///     async fn stream(&self, client: &Client, _: Request, res: Response) {
+///         let n = client.requests.fetch_add(1, Ordering::Relaxed);
+///         let _ = res.text(format!("Connection {}, request {n}", client.id)).await;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> std::io::Result<()> {
+///     let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+///     let server = Server::bind("127.0.0.1:4433", conf).await?;
+///     let app = Arc::new(App::default());
+///     loop {
+///         if let Ok((conn, _)) = server.accept().await {
+///             conn.serve(Arc::clone(&app));
+///         }
+///     }
+/// }
+/// ```
+pub trait Handler: Send + Sync + 'static {
+    /// State of a connection, shared by all its streams.
+    type State: Send + Sync + 'static;
+
+    /// Called once the connection is established, creating its state.
+    ///
+    /// Returning `Err(reason)` rejects the connection, closing it with a `GOAWAY` frame carrying `reason`.
+    fn connect(
+        &self,
+        info: &ConnInfo,
+    ) -> impl Future<Output = Result<Self::State, h2::Reason>> + Send;
+
+    /// Called for each stream within a connection and is responsible for processing the stream
+    fn stream(
+        &self,
+        state: &Self::State,
+        req: Request,
+        res: Response,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// See [Incoming::on_stream_reset]
+    #[inline]
+    fn on_stream_reset(&self, state: &Self::State, stream_id: u32, reason: h2::Reason) {
+        let _ = (state, stream_id, reason);
+    }
+
+    /// See [Incoming::on_goaway]
+    #[inline]
+    fn on_goaway(&self, state: &Self::State, reason: h2::Reason, origin: Origin) {
+        let _ = (state, reason, origin);
+    }
+
+    /// See [Incoming::on_settings]
+    #[inline]
+    fn on_settings(&self, state: &Self::State, settings: &PeerSettings) {
+        let _ = (state, settings);
+    }
+
+    /// Called when disconnected, with the reason the connection ended.
+    ///
+    /// It is not called for rejected connections.
+    #[inline]
+    fn disconnect(
+        &self,
+        state: &Self::State,
+        result: &Result<(), ConnError>,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = (state, result);
+        async {}
+    }
+}
+
+/// Adapts a [Handler] to [Incoming], for a single connection.
+pub(crate) struct PerConn<H: Handler> {
+    handler: Arc<H>,
+    state: Arc<OnceLock<H::State>>,
+}
+
+impl<H: Handler> PerConn<H> {
+    pub(crate) fn new(handler: Arc<H>) -> Self {
+        Self {
+            handler,
+            state: Arc::new(OnceLock::new()),
+        }
+    }
+}
+
+impl<H: Handler> Clone for PerConn<H> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: Arc::clone(&self.handler),
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<H: Handler> Incoming for PerConn<H> {
+    async fn stream(self, req: Request, res: Response) {
+        // Streams of a rejected connection are dropped.
+        if let Some(state) = self.state.get() {
+            self.handler.stream(state, req, res).await;
+        }
+    }
+
+    async fn on_connect(self, info: ConnInfo) -> Result<(), h2::Reason> {
+        let state = self.handler.connect(&info).await?;
+        let _ = self.state.set(state);
+        Ok(())
+    }
+
+    fn on_stream_reset(&self, stream_id: u32, reason: h2::Reason) {
+        if let Some(state) = self.state.get() {
+            self.handler.on_stream_reset(state, stream_id, reason);
+        }
+    }
+
+    fn on_goaway(&self, reason: h2::Reason, origin: Origin) {
+        if let Some(state) = self.state.get() {
+            self.handler.on_goaway(state, reason, origin);
+        }
+    }
+
+    fn on_settings(&self, settings: &PeerSettings) {
+        if let Some(state) = self.state.get() {
+            self.handler.on_settings(state, settings);
+        }
+    }
+
+    async fn disconnect(self, result: &Result<(), ConnError>) {
+        if let Some(state) = self.state.get() {
+            self.handler.disconnect(state, result).await;
+        }
+    }
+}
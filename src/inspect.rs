@@ -0,0 +1,483 @@
+use super::*;
+use crate::{flood::StreamTracker, timeout::IdleTimer};
+use h2::Reason;
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Length of the client connection preface, `PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n`.
+const PREFACE_LEN: usize = 24;
+
+/// Length of a frame header.
+const HEADER_LEN: usize = 9;
+
+/// Events are dropped once this many are waiting, as they are only consumed by [Conn::incoming].
+const MAX_EVENTS: usize = 256;
+
+/// Only the beginning of a `SETTINGS` payload is parsed, which fits all the known settings.
+const MAX_SETTINGS_LEN: usize = 6 * 16;
+
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const GOAWAY: u8 = 0x7;
+const CONTINUATION: u8 = 0x9;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+
+/// Settings sent by the peer in a `SETTINGS` frame, see [Incoming::on_settings].
+///
+/// A setting is `None` if the frame did not include it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerSettings {
+    /// `SETTINGS_HEADER_TABLE_SIZE`
+    pub header_table_size: Option<u32>,
+    /// `SETTINGS_ENABLE_PUSH`
+    pub enable_push: Option<bool>,
+    /// `SETTINGS_MAX_CONCURRENT_STREAMS`
+    pub max_concurrent_streams: Option<u32>,
+    /// `SETTINGS_INITIAL_WINDOW_SIZE`
+    pub initial_window_size: Option<u32>,
+    /// `SETTINGS_MAX_FRAME_SIZE`
+    pub max_frame_size: Option<u32>,
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`
+    pub max_header_list_size: Option<u32>,
+    /// `SETTINGS_ENABLE_CONNECT_PROTOCOL`
+    pub enable_connect_protocol: Option<bool>,
+}
+
+/// A protocol event of a connection, reported to the [Incoming] hooks.
+#[derive(Debug)]
+pub(crate) enum ConnEvent {
+    Settings(PeerSettings),
+    GoAway(Reason, Origin),
+    Reset(u32, Reason),
+}
+
+/// Events waiting to be reported.
+#[derive(Debug, Default)]
+pub(crate) struct Events {
+    queue: Mutex<VecDeque<ConnEvent>>,
+}
+
+impl Events {
+    pub(crate) fn push(&self, event: ConnEvent) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() < MAX_EVENTS {
+            queue.push_back(event);
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<ConnEvent> {
+        self.queue.lock().unwrap().pop_front()
+    }
+}
+
+/// The transport of a [Conn], it wraps the `IO` passed to [Builder::handshake].
+///
+/// It can parse the frames received from the peer, to report the `SETTINGS`, `GOAWAY` and `RST_STREAM`
+/// frames that [h2] does not expose, and to time the header blocks of requests. Frames are only parsed if they are needed by
+/// [Builder::connection_events], [Builder::max_reset_rate], [Builder::max_settings_rate], [Builder::request_header_timeout]
+/// or the `tracing` feature, otherwise the bytes are passed through.
+#[derive(Debug)]
+pub struct Inspect<IO> {
+    io: IO,
+    parser: Option<Parser>,
+    events: Option<Arc<Events>>,
+    streams: Arc<StreamTracker>,
+    /// Limits the time to receive a header block, see [Builder::request_header_timeout].
+    header_timer: IdleTimer,
+    /// The header block that `header_timer` is running for.
+    header_block: u64,
+}
+
+#[derive(Debug, Default)]
+struct Parser {
+    preface: usize,
+    header: [u8; HEADER_LEN],
+    header_len: usize,
+    frame: Option<Frame>,
+    /// A header block is being received, from its `HEADERS` frame until `END_HEADERS`.
+    in_header_block: bool,
+    /// Number of header blocks started.
+    header_blocks: u64,
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    remaining: usize,
+    /// Number of payload bytes to keep.
+    keep: usize,
+    payload: Vec<u8>,
+}
+
+impl<IO> Inspect<IO> {
+    pub(crate) fn new(
+        io: IO,
+        events: Option<Arc<Events>>,
+        streams: Arc<StreamTracker>,
+        header_timeout: Option<Duration>,
+    ) -> Self {
+        let is_parsed = events.is_some()
+            || streams.limits_frames()
+            || header_timeout.is_some()
+            || cfg!(feature = "tracing");
+        Self {
+            io,
+            parser: is_parsed.then(Parser::default),
+            events,
+            streams,
+            header_timer: IdleTimer::new(header_timeout),
+            header_block: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying transport.
+    #[inline]
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    /// Returns a mutable reference to the underlying transport.
+    ///
+    /// Reading from it directly would corrupt the HTTP/2 connection.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+}
+
+impl Parser {
+    fn feed(&mut self, mut data: &[u8], report: &mut impl FnMut(ConnEvent)) {
+        if self.preface < PREFACE_LEN {
+            let n = (PREFACE_LEN - self.preface).min(data.len());
+            self.preface += n;
+            data = &data[n..];
+        }
+        while !data.is_empty() {
+            let Some(frame) = &mut self.frame else {
+                let n = (HEADER_LEN - self.header_len).min(data.len());
+                self.header[self.header_len..self.header_len + n].copy_from_slice(&data[..n]);
+                self.header_len += n;
+                data = &data[n..];
+                if self.header_len == HEADER_LEN {
+                    self.header_len = 0;
+                    self.start_frame(report);
+                }
+                continue;
+            };
+            let n = frame.remaining.min(data.len());
+            let keep = (frame.keep - frame.payload.len()).min(n);
+            frame.payload.extend_from_slice(&data[..keep]);
+            frame.remaining -= n;
+            data = &data[n..];
+            if frame.remaining == 0 {
+                if let Some(frame) = self.frame.take() {
+                    self.end_frame(frame, report);
+                }
+            }
+        }
+    }
+
+    fn start_frame(&mut self, report: &mut impl FnMut(ConnEvent)) {
+        let [l0, l1, l2, kind, flags, s0, s1, s2, s3] = self.header;
+        let len = u32::from_be_bytes([0, l0, l1, l2]) as usize;
+        if kind == HEADERS {
+            self.in_header_block = true;
+            self.header_blocks += 1;
+        }
+        let keep = match kind {
+            SETTINGS if flags & ACK == 0 => MAX_SETTINGS_LEN,
+            RST_STREAM => 4,
+            GOAWAY => 8,
+            _ => 0,
+        };
+        let frame = Frame {
+            kind,
+            flags,
+            stream_id: u32::from_be_bytes([s0, s1, s2, s3]) & 0x7FFF_FFFF,
+            remaining: len,
+            keep: keep.min(len),
+            payload: Vec::new(),
+        };
+        if len == 0 {
+            self.end_frame(frame, report);
+        } else {
+            self.frame = Some(frame);
+        }
+    }
+
+    fn end_frame(&mut self, frame: Frame, report: &mut impl FnMut(ConnEvent)) {
+        if matches!(frame.kind, HEADERS | CONTINUATION) && frame.flags & END_HEADERS != 0 {
+            self.in_header_block = false;
+        }
+        if let Some(event) = frame.finish() {
+            report(event);
+        }
+    }
+}
+
+impl Frame {
+    fn finish(self) -> Option<ConnEvent> {
+        let payload = &self.payload[..];
+        let u32_at = |i: usize| {
+            u32::from_be_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]])
+        };
+        match self.kind {
+            SETTINGS if self.flags & ACK == 0 => {
+                let mut settings = PeerSettings::default();
+                for entry in payload.chunks_exact(6) {
+                    let value = u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]);
+                    match u16::from_be_bytes([entry[0], entry[1]]) {
+                        0x1 => settings.header_table_size = Some(value),
+                        0x2 => settings.enable_push = Some(value != 0),
+                        0x3 => settings.max_concurrent_streams = Some(value),
+                        0x4 => settings.initial_window_size = Some(value),
+                        0x5 => settings.max_frame_size = Some(value),
+                        0x6 => settings.max_header_list_size = Some(value),
+                        0x8 => settings.enable_connect_protocol = Some(value != 0),
+                        _ => {}
+                    }
+                }
+                Some(ConnEvent::Settings(settings))
+            }
+            RST_STREAM if payload.len() == 4 => {
+                Some(ConnEvent::Reset(self.stream_id, Reason::from(u32_at(0))))
+            }
+            GOAWAY if payload.len() == 8 => {
+                Some(ConnEvent::GoAway(Reason::from(u32_at(4)), Origin::Remote))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Inspect<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.io).poll_read(cx, buf);
+        let Some(parser) = &mut this.parser else {
+            return result;
+        };
+        if let Poll::Ready(Ok(())) = &result {
+            let (events, streams) = (&this.events, &this.streams);
+            parser.feed(&buf.filled()[filled..], &mut |event| {
+                match event {
+                    ConnEvent::Reset(_, reason) => streams.on_peer_reset(reason),
+                    ConnEvent::Settings(_) => streams.on_peer_settings(),
+                    ConnEvent::GoAway(..) => {}
+                }
+                if let Some(events) = events {
+                    events.push(event);
+                }
+            });
+        }
+        if !parser.in_header_block || parser.header_blocks != this.header_block {
+            this.header_block = parser.header_blocks;
+            this.header_timer.reset();
+        }
+        if parser.in_header_block && this.header_timer.poll_elapsed(cx).is_ready() {
+            this.header_timer = IdleTimer::default();
+            this.streams.on_header_timeout();
+            // The connection is closed by `Conn`, which may only poll this transport again once woken.
+            cx.waker().wake_by_ref();
+        }
+        result
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Inspect<IO> {
+    #[inline]
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    #[inline]
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write_vectored(cx, bufs)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+    fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[kind, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn setting(id: u16, value: u32) -> Vec<u8> {
+        [&id.to_be_bytes()[..], &value.to_be_bytes()].concat()
+    }
+
+    /// Feeds `bytes` in chunks of `chunk` bytes.
+    fn parse(bytes: &[u8], chunk: usize) -> Vec<ConnEvent> {
+        let mut parser = Parser::default();
+        let mut events = Vec::new();
+        for data in bytes.chunks(chunk) {
+            parser.feed(data, &mut |event| events.push(event));
+        }
+        events
+    }
+
+    fn connection() -> Vec<u8> {
+        let settings = [
+            setting(0x3, 100),
+            setting(0x4, 65535),
+            setting(0x2, 0),
+            setting(0xFF, 1),
+        ];
+        [
+            PREFACE,
+            &frame(SETTINGS, 0, 0, &settings.concat()),
+            &frame(0x1, 0x5, 1, &[0x82, 0x87, 0x84]),
+            &frame(SETTINGS, ACK, 0, &[]),
+            &frame(0x0, 0x0, 1, &[0; 1000]),
+            &frame(RST_STREAM, 0, 1, &8u32.to_be_bytes()),
+            &frame(GOAWAY, 0, 0, &[0, 0, 0, 1, 0, 0, 0, 1]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn parses_frames() {
+        for chunk in [1, 7, 9, 24, 100, usize::MAX] {
+            let events = parse(&connection(), chunk);
+            assert_eq!(events.len(), 3, "chunk size {chunk}");
+            let ConnEvent::Settings(settings) = &events[0] else {
+                panic!("expected SETTINGS, got {:?}", events[0]);
+            };
+            let expected = PeerSettings {
+                max_concurrent_streams: Some(100),
+                initial_window_size: Some(65535),
+                enable_push: Some(false),
+                ..PeerSettings::default()
+            };
+            assert_eq!(*settings, expected);
+            assert!(matches!(events[1], ConnEvent::Reset(1, Reason::CANCEL)));
+            assert!(matches!(
+                events[2],
+                ConnEvent::GoAway(Reason::PROTOCOL_ERROR, Origin::Remote)
+            ));
+        }
+    }
+
+    #[test]
+    fn empty_settings() {
+        let bytes = [PREFACE, &frame(SETTINGS, 0, 0, &[])].concat();
+        let events = parse(&bytes, usize::MAX);
+        assert!(matches!(&events[..], [ConnEvent::Settings(s)] if *s == PeerSettings::default()));
+    }
+
+    #[test]
+    fn ignores_malformed_frames() {
+        let bytes = [
+            PREFACE,
+            &frame(RST_STREAM, 0, 1, &[0, 0, 8]),
+            &frame(GOAWAY, 0, 0, &[0, 0, 0, 1]),
+        ]
+        .concat();
+        assert!(parse(&bytes, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn keeps_a_bounded_settings_payload() {
+        let entries: Vec<u8> = (0..1000).flat_map(|_| setting(0x5, 16384)).collect();
+        let bytes = [
+            PREFACE,
+            &frame(SETTINGS, 0, 0, &entries),
+            &frame(RST_STREAM, 0, 3, &2u32.to_be_bytes()),
+        ]
+        .concat();
+        let mut parser = Parser::default();
+        let mut events = Vec::new();
+        for data in bytes.chunks(512) {
+            parser.feed(data, &mut |event| events.push(event));
+            assert!(parser
+                .frame
+                .as_ref()
+                .is_none_or(|f| f.payload.len() <= MAX_SETTINGS_LEN));
+        }
+        assert!(matches!(&events[0], ConnEvent::Settings(s) if s.max_frame_size == Some(16384)));
+        assert!(matches!(
+            events[1],
+            ConnEvent::Reset(3, Reason::INTERNAL_ERROR)
+        ));
+    }
+
+    #[test]
+    fn tracks_header_blocks() {
+        let mut parser = Parser::default();
+        let mut feed = |data: &[u8]| {
+            parser.feed(data, &mut |_| {});
+            (parser.in_header_block, parser.header_blocks)
+        };
+        assert_eq!(feed(PREFACE), (false, 0));
+        assert_eq!(feed(&frame(HEADERS, 0x1, 1, &[0x82])), (true, 1));
+        assert_eq!(feed(&frame(CONTINUATION, 0, 1, &[0x87])), (true, 1));
+        // A partial frame does not end the block.
+        let end = frame(CONTINUATION, END_HEADERS, 1, &[0x84, 0x41]);
+        assert_eq!(feed(&end[..10]), (true, 1));
+        assert_eq!(feed(&end[10..]), (false, 1));
+        assert_eq!(
+            feed(&frame(HEADERS, 0x5, 3, &[0x82, 0x87, 0x84])),
+            (false, 2)
+        );
+        assert_eq!(feed(&frame(HEADERS, 0x1, 5, &[])), (true, 3));
+    }
+
+    #[test]
+    fn bounded_queue() {
+        let events = Events::default();
+        for id in 0..MAX_EVENTS as u32 + 10 {
+            events.push(ConnEvent::Reset(id, Reason::CANCEL));
+        }
+        let mut count = 0;
+        while events.pop().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, MAX_EVENTS);
+    }
+}
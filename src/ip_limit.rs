@@ -0,0 +1,217 @@
+use super::*;
+use crate::rate_limit::TokenBucket;
+use http::{header, HeaderValue, StatusCode};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Entries are purged once the map grows beyond this size.
+const MIN_PURGE_SIZE: usize = 1024;
+
+/// Per client limits, see [Builder::max_connections_per_ip].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IpLimits {
+    pub(crate) max_conns: Option<usize>,
+    pub(crate) request_rate: Option<(u32, Duration)>,
+    pub(crate) ipv4_prefix: u8,
+    pub(crate) ipv6_prefix: u8,
+}
+
+impl Default for IpLimits {
+    fn default() -> Self {
+        Self {
+            max_conns: None,
+            request_rate: None,
+            ipv4_prefix: 32,
+            ipv6_prefix: 128,
+        }
+    }
+}
+
+/// Tracks the clients of a server, keyed by IP address or subnet.
+#[derive(Debug)]
+pub(crate) struct IpLimiter {
+    limits: IpLimits,
+    clients: Mutex<Clients>,
+}
+
+#[derive(Debug)]
+struct Clients {
+    map: HashMap<IpAddr, Client>,
+    purge_at: usize,
+}
+
+#[derive(Debug)]
+struct Client {
+    conns: usize,
+    requests: Option<TokenBucket>,
+}
+
+/// A connection of a client, it is released when dropped.
+#[derive(Debug)]
+pub(crate) struct ClientConn {
+    limiter: Arc<IpLimiter>,
+    key: IpAddr,
+}
+
+impl IpLimiter {
+    pub(crate) fn new(limits: IpLimits) -> Option<Arc<Self>> {
+        if limits.max_conns.is_none() && limits.request_rate.is_none() {
+            return None;
+        }
+        Some(Arc::new(Self {
+            limits,
+            clients: Mutex::new(Clients {
+                map: HashMap::new(),
+                purge_at: MIN_PURGE_SIZE,
+            }),
+        }))
+    }
+
+    fn key(&self, ip: IpAddr) -> IpAddr {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.limits.ipv4_prefix.min(32)));
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask.unwrap_or(0)))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.limits.ipv6_prefix.min(128)));
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask.unwrap_or(0)))
+            }
+        }
+    }
+
+    /// Registers a new connection from `ip`, returns `None` if the client has too many connections.
+    pub(crate) fn connect(self: &Arc<Self>, ip: IpAddr) -> Option<ClientConn> {
+        let key = self.key(ip);
+        let mut clients = self.clients.lock().unwrap();
+        if clients.map.len() >= clients.purge_at {
+            // Forget idle clients, whose request budget is fully refilled.
+            clients.map.retain(|_, client| {
+                client.conns > 0 || client.requests.as_mut().is_some_and(|r| !r.is_full())
+            });
+            clients.purge_at = (clients.map.len() * 2).max(MIN_PURGE_SIZE);
+        }
+        let client = clients.map.entry(key).or_insert_with(|| Client {
+            conns: 0,
+            requests: self
+                .limits
+                .request_rate
+                .map(|(count, period)| TokenBucket::new(count, period)),
+        });
+        if self.limits.max_conns.is_some_and(|max| client.conns >= max) {
+            return None;
+        }
+        client.conns += 1;
+        Some(ClientConn {
+            limiter: Arc::clone(self),
+            key,
+        })
+    }
+}
+
+impl ClientConn {
+    /// Takes a request from the client budget, returns the time to wait before retrying if it is exhausted.
+    pub(crate) fn try_request(&self) -> Result<(), Duration> {
+        let mut clients = self.limiter.clients.lock().unwrap();
+        let Some(requests) = clients
+            .map
+            .get_mut(&self.key)
+            .and_then(|client| client.requests.as_mut())
+        else {
+            return Ok(());
+        };
+        if !requests.try_acquire() {
+            return Err(requests.retry_after());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ClientConn {
+    fn drop(&mut self) {
+        let mut clients = self.limiter.clients.lock().unwrap();
+        if let Some(client) = clients.map.get_mut(&self.key) {
+            client.conns -= 1;
+        }
+    }
+}
+
+/// Responds with `429 Too Many Requests`.
+pub(crate) fn too_many_requests(
+    sender: &mut h2::server::SendResponse<Bytes>,
+    retry_after: Duration,
+) {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = http::Response::new(());
+    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    let headers = response.headers_mut();
+    headers.insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+    let _ = sender.send_response(response, true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(ipv4_prefix: u8, ipv6_prefix: u8) -> Arc<IpLimiter> {
+        IpLimiter::new(IpLimits {
+            max_conns: Some(2),
+            ipv4_prefix,
+            ipv6_prefix,
+            ..IpLimits::default()
+        })
+        .unwrap()
+    }
+
+    fn key(limiter: &IpLimiter, ip: &str) -> String {
+        limiter.key(ip.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn full_addresses_by_default() {
+        let limiter = limiter(32, 128);
+        assert_eq!(key(&limiter, "192.168.1.77"), "192.168.1.77");
+        assert_eq!(key(&limiter, "2001:db8::1"), "2001:db8::1");
+    }
+
+    #[test]
+    fn masks_subnets() {
+        let narrow = limiter(24, 64);
+        assert_eq!(key(&narrow, "192.168.1.77"), "192.168.1.0");
+        assert_eq!(key(&narrow, "2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::");
+
+        let wide = limiter(20, 48);
+        assert_eq!(key(&wide, "10.1.31.1"), "10.1.16.0");
+        assert_eq!(key(&wide, "2001:db8:1:2:3:4:5:6"), "2001:db8:1::");
+    }
+
+    #[test]
+    fn zero_prefix_groups_every_client() {
+        let limiter = limiter(0, 0);
+        assert_eq!(key(&limiter, "192.168.1.77"), "0.0.0.0");
+        assert_eq!(key(&limiter, "2001:db8::1"), "::");
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_use_the_ipv4_prefix() {
+        let limiter = limiter(24, 128);
+        assert_eq!(key(&limiter, "::ffff:192.168.1.77"), "192.168.1.0");
+    }
+
+    #[test]
+    fn limits_connections_per_subnet() {
+        let limiter = limiter(24, 128);
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        let first = limiter.connect(ip("10.0.0.1")).unwrap();
+        let _second = limiter.connect(ip("10.0.0.2")).unwrap();
+        assert!(limiter.connect(ip("10.0.0.3")).is_none());
+        assert!(limiter.connect(ip("10.0.1.1")).is_some());
+        drop(first);
+        assert!(limiter.connect(ip("10.0.0.3")).is_some());
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+use h2::{Ping, PingPong};
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::ready,
+    time::Duration,
+};
+use tokio::time::{sleep, Instant, Sleep};
+
+/// Default time to wait for a `PING` acknowledgement: `20` seconds
+pub(crate) const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Round-trip time of a connection, updated on each `PING` acknowledged by the peer.
+///
+/// It is a shared handle, available to services while the connection is served,
+/// see [ConnInfo::rtt] and [Request::rtt].
+#[derive(Clone, Default)]
+pub struct Rtt {
+    nanos: Arc<AtomicU64>,
+}
+
+impl Rtt {
+    /// Returns the round-trip time of the last acknowledged `PING`,
+    /// `None` if none was acknowledged yet, or keep-alive is disabled.
+    pub fn get(&self) -> Option<Duration> {
+        match self.nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    fn set(&self, rtt: Duration) {
+        let nanos = u64::try_from(rtt.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.store(nanos.max(1), Ordering::Relaxed);
+    }
+}
+
+impl fmt::Debug for Rtt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+/// Sends a `PING` frame every `interval`, and measures the round-trip time of its acknowledgement.
+pub(crate) struct KeepAlive {
+    ping_pong: PingPong,
+    interval: Duration,
+    timeout: Duration,
+    sleep: Pin<Box<Sleep>>,
+    sent_at: Option<Instant>,
+    rtt: Rtt,
+}
+
+impl KeepAlive {
+    pub(crate) fn new(ping_pong: PingPong, interval: Duration, timeout: Duration) -> Self {
+        Self {
+            ping_pong,
+            interval,
+            timeout,
+            sleep: Box::pin(sleep(interval)),
+            sent_at: None,
+            rtt: Rtt::default(),
+        }
+    }
+
+    /// Round-trip time of the last acknowledged `PING`.
+    #[inline]
+    pub(crate) fn rtt(&self) -> &Rtt {
+        &self.rtt
+    }
+
+    /// Sends pings and receives pongs, it is ready once the peer failed to answer a ping in time.
+    pub(crate) fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            match self.sent_at {
+                Some(sent_at) => {
+                    if let Poll::Ready(pong) = self.ping_pong.poll_pong(cx) {
+                        if pong.is_err() {
+                            return Poll::Ready(());
+                        }
+                        let now = Instant::now();
+                        self.rtt.set(now - sent_at);
+                        self.sent_at = None;
+                        self.sleep.as_mut().reset(now + self.interval);
+                        continue;
+                    }
+                    ready!(self.sleep.as_mut().poll(cx));
+                    return Poll::Ready(());
+                }
+                None => {
+                    ready!(self.sleep.as_mut().poll(cx));
+                    if self.ping_pong.send_ping(Ping::opaque()).is_err() {
+                        return Poll::Ready(());
+                    }
+                    let now = Instant::now();
+                    self.sent_at = Some(now);
+                    self.sleep.as_mut().reset(now + self.timeout);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for KeepAlive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeepAlive")
+            .field("interval", &self.interval)
+            .field("timeout", &self.timeout)
+            .field("rtt", &self.rtt)
+            .finish()
+    }
+}
@@ -6,15 +6,65 @@ pub use h2;
 pub use http;
 pub use tokio_tls_listener;
 
+#[macro_use]
+mod trace;
+
+mod access_log;
+mod async_io;
+mod body;
+mod budget;
+mod buffered;
+mod builder;
+#[cfg(any(feature = "gzip", feature = "br", feature = "zstd"))]
+mod compression;
+mod conn_error;
+#[cfg(any(feature = "json", feature = "form", feature = "query"))]
+mod extract;
+#[cfg(feature = "fs")]
+mod file_body;
+mod flood;
 mod graceful_shutdown;
+mod handler;
+mod inspect;
+mod ip_limit;
+mod keep_alive;
+mod metrics;
+mod proxy_protocol;
+mod range;
+mod rate_limit;
 mod request;
 mod response;
+#[cfg(feature = "fs")]
+mod serve_dir;
 mod server;
+mod sse;
+mod stats;
+mod timeout;
+mod tunnel;
 
+pub use access_log::{AccessLog, LogFormat};
+pub use async_io::{BodyReader, BodyWriter};
+pub use body::{BodyError, Collected, DEFAULT_BODY_LIMIT};
+pub use buffered::BufResponder;
+pub use builder::Builder;
+#[cfg(any(feature = "gzip", feature = "br", feature = "zstd"))]
+pub use compression::{CompressedResponder, Compression, DecompressStream, Encoding};
+pub use conn_error::{ConnError, Origin};
+#[cfg(feature = "fs")]
+pub use file_body::FileBody;
 pub use graceful_shutdown::GracefulShutdown;
+pub use handler::Handler;
+pub use inspect::{Inspect, PeerSettings};
+pub use keep_alive::Rtt;
+pub use metrics::Metrics;
+pub use proxy_protocol::{ProxyHeader, ProxyProtocol, Tlv};
 pub use request::*;
 pub use response::*;
+#[cfg(feature = "fs")]
+pub use serve_dir::{ServeDir, ServeFile};
 pub use server::*;
+pub use sse::{Event, EventStream};
+pub use tunnel::Tunnel;
 
 use bytes::Bytes;
 use std::{
@@ -31,17 +81,60 @@ type BoxErr = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T, E = h2::Error> = std::result::Result<T, E>;
 
 fn io_err(error: impl Into<BoxErr>) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, error)
+    std::io::Error::other(error)
 }
 
 /// Serving incoming connections and handling streams using the provided callbacks.
+///
+/// The connection hooks (`on_*` and [Incoming::disconnect]) are called by [Conn::incoming].
+/// [Incoming::on_stream_reset], [Incoming::on_goaway] and [Incoming::on_settings]
+/// also require [Builder::connection_events].
 pub trait Incoming: Clone + Send + 'static {
     /// Called for each stream within a connection and is responsible for processing the stream
     fn stream(self, req: Request, res: Response) -> impl Future<Output = ()> + Send;
 
+    /// Called once the connection is established, before any stream is handled.
+    ///
+    /// Returning `Err(reason)` rejects the connection, closing it with a `GOAWAY` frame carrying `reason`.
+    #[inline]
+    fn on_connect(self, info: ConnInfo) -> impl Future<Output = Result<(), h2::Reason>> + Send {
+        let _ = info;
+        async { Ok(()) }
+    }
+
+    /// Called when the peer resets a stream with a `RST_STREAM` frame.
+    #[inline]
+    fn on_stream_reset(&self, stream_id: u32, reason: h2::Reason) {
+        let _ = (stream_id, reason);
+    }
+
+    /// Called when a `GOAWAY` frame is sent or received.
+    ///
+    /// A graceful shutdown is reported once, with `NO_ERROR`.
+    #[inline]
+    fn on_goaway(&self, reason: h2::Reason, origin: Origin) {
+        let _ = (reason, origin);
+    }
+
+    /// Called for each `SETTINGS` frame received from the peer, starting with the one of the connection preface.
+    #[inline]
+    fn on_settings(&self, settings: &PeerSettings) {
+        let _ = settings;
+    }
+
     /// Called when disconnected
     #[inline]
     fn close(self) -> impl Future<Output = ()> + Send {
         async {}
     }
-}
\ No newline at end of file
+
+    /// Called when disconnected, with the reason the connection ended:
+    /// `Ok(())` if it was closed cleanly, or the [ConnError] that terminated it.
+    ///
+    /// By default, it calls [Incoming::close].
+    #[inline]
+    fn disconnect(self, result: &Result<(), ConnError>) -> impl Future<Output = ()> + Send {
+        let _ = result;
+        self.close()
+    }
+}
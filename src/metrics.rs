@@ -0,0 +1,331 @@
+use super::*;
+use crate::stats::StreamStats;
+use http::StatusCode;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the request duration histogram buckets, in seconds.
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Server metrics, exposed in the Prometheus text format.
+///
+/// Metrics are collected by the servers and connections configured with [Builder::metrics].
+/// It is cheap to clone, all clones share the same metrics.
+///
+/// | Name | Type | Description |
+/// | ---- | ---- | ----------- |
+/// | `h2x_connections_accepted_total` | counter | TCP connections accepted by [Server::accept] |
+/// | `h2x_connections_active` | gauge | Open HTTP/2 connections |
+/// | `h2x_handshake_failures_total{reason}` | counter | Connections that failed to be established |
+/// | `h2x_streams_total{status}` | counter | Streams by response status, `none` if no response was sent |
+/// | `h2x_request_duration_seconds` | histogram | Time from receiving the request to the end of the stream |
+/// | `h2x_received_bytes_total` | counter | Request body bytes |
+/// | `h2x_sent_bytes_total` | counter | Response body bytes |
+/// | `h2x_flow_control_stalls_total` | counter | Writes that waited for the peer to grant send capacity |
+///
+/// The `reason` of handshake failures is one of `proxy_protocol`, `too_many_connections`,
+/// `tls`, `timeout` or `http2`.
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// #[derive(Clone)]
+/// struct App {
+///     metrics: Metrics,
+/// }
+///
+/// impl Incoming for App {
+///     async fn stream(self, req: Request, res: Response) {
+///         let _ = match req.uri.path() {
+///             "/metrics" => self.metrics.serve(res).await,
+///             _ => res.text("Hello, World").await,
+///         };
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> std::io::Result<()> {
+///     let metrics = Metrics::new();
+///     let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+///     let server = Server::builder()
+///         .metrics(&metrics)
+///         .bind("127.0.0.1:4433", conf)
+///         .await?;
+///     loop {
+///         if let Ok((conn, _)) = server.accept().await {
+///             conn.incoming(App { metrics: metrics.clone() });
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Registry>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    connections_accepted: AtomicU64,
+    connections_active: AtomicI64,
+    handshake_failures: [AtomicU64; HandshakeFailure::ALL.len()],
+    streams: Mutex<BTreeMap<u16, u64>>,
+    duration: Histogram,
+    received_bytes: AtomicU64,
+    sent_bytes: AtomicU64,
+    flow_control_stalls: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+/// Why a connection failed to be established.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HandshakeFailure {
+    ProxyProtocol,
+    TooManyConnections,
+    Tls,
+    Timeout,
+    Http2,
+}
+
+impl HandshakeFailure {
+    const ALL: [Self; 5] = [
+        Self::ProxyProtocol,
+        Self::TooManyConnections,
+        Self::Tls,
+        Self::Timeout,
+        Self::Http2,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ProxyProtocol => "proxy_protocol",
+            Self::TooManyConnections => "too_many_connections",
+            Self::Tls => "tls",
+            Self::Timeout => "timeout",
+            Self::Http2 => "http2",
+        }
+    }
+}
+
+/// An open connection, counted in `h2x_connections_active` until it is dropped.
+#[derive(Debug)]
+pub(crate) struct ActiveConn {
+    pub(crate) metrics: Metrics,
+}
+
+impl Metrics {
+    /// Creates a new, empty set of metrics.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodes the metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let m = &self.inner;
+        let mut out = String::new();
+        let accepted = load(&m.connections_accepted);
+        header(
+            &mut out,
+            "h2x_connections_accepted_total",
+            "counter",
+            "TCP connections accepted.",
+        );
+        let _ = writeln!(out, "h2x_connections_accepted_total {accepted}");
+
+        let active = m.connections_active.load(Ordering::Relaxed);
+        header(
+            &mut out,
+            "h2x_connections_active",
+            "gauge",
+            "Open HTTP/2 connections.",
+        );
+        let _ = writeln!(out, "h2x_connections_active {active}");
+
+        header(
+            &mut out,
+            "h2x_handshake_failures_total",
+            "counter",
+            "Connections that failed to be established.",
+        );
+        for (failure, count) in HandshakeFailure::ALL.iter().zip(&m.handshake_failures) {
+            let (reason, count) = (failure.as_str(), load(count));
+            let _ = writeln!(
+                out,
+                "h2x_handshake_failures_total{{reason=\"{reason}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "h2x_streams_total",
+            "counter",
+            "Streams by response status.",
+        );
+        for (status, count) in m.streams.lock().unwrap().iter() {
+            let _ = match status {
+                0 => writeln!(out, "h2x_streams_total{{status=\"none\"}} {count}"),
+                _ => writeln!(out, "h2x_streams_total{{status=\"{status}\"}} {count}"),
+            };
+        }
+
+        let name = "h2x_request_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time from receiving the request to the end of the stream.",
+        );
+        let mut cumulative = 0;
+        for (le, count) in DURATION_BUCKETS.iter().zip(&m.duration.buckets) {
+            cumulative += load(count);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let count = load(&m.duration.count);
+        let sum = Duration::from_nanos(load(&m.duration.sum_nanos)).as_secs_f64();
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {sum}\n{name}_count {count}");
+
+        let received = load(&m.received_bytes);
+        header(
+            &mut out,
+            "h2x_received_bytes_total",
+            "counter",
+            "Request body bytes received.",
+        );
+        let _ = writeln!(out, "h2x_received_bytes_total {received}");
+
+        let sent = load(&m.sent_bytes);
+        header(
+            &mut out,
+            "h2x_sent_bytes_total",
+            "counter",
+            "Response body bytes sent.",
+        );
+        let _ = writeln!(out, "h2x_sent_bytes_total {sent}");
+
+        let stalls = load(&m.flow_control_stalls);
+        header(
+            &mut out,
+            "h2x_flow_control_stalls_total",
+            "counter",
+            "Writes that waited for the peer to grant send capacity.",
+        );
+        let _ = writeln!(out, "h2x_flow_control_stalls_total {stalls}");
+        out
+    }
+
+    /// Responds with the metrics in the Prometheus text exposition format.
+    pub async fn serve(&self, res: Response) -> Result<()> {
+        res.write_typed("text/plain; version=0.0.4; charset=utf-8", self.encode())
+            .await
+    }
+
+    pub(crate) fn on_accept(&self) {
+        self.inner
+            .connections_accepted
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_handshake_failure(&self, failure: HandshakeFailure) {
+        self.inner.handshake_failures[failure as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_connect(&self) -> ActiveConn {
+        self.inner
+            .connections_active
+            .fetch_add(1, Ordering::Relaxed);
+        ActiveConn {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Counts a stream that was answered without a handler, such as `429 Too Many Requests`.
+    pub(crate) fn on_status(&self, status: StatusCode) {
+        *self
+            .inner
+            .streams
+            .lock()
+            .unwrap()
+            .entry(status.as_u16())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn on_stream_end(&self, stats: &StreamStats, duration: Duration) {
+        *self
+            .inner
+            .streams
+            .lock()
+            .unwrap()
+            .entry(stats.status().unwrap_or(0))
+            .or_default() += 1;
+
+        let histogram = &self.inner.duration;
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|le| secs <= *le) {
+            histogram.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        histogram.count.fetch_add(1, Ordering::Relaxed);
+        histogram.sum_nanos.fetch_add(
+            u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn on_bytes_received(&self, len: usize) {
+        self.inner
+            .received_bytes
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_bytes_sent(&self, len: usize) {
+        self.inner
+            .sent_bytes
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_flow_control_stall(&self) {
+        self.inner
+            .flow_control_stalls
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Incoming for Metrics {
+    async fn stream(self, _: Request, res: Response) {
+        let _ = self.serve(res).await;
+    }
+}
+
+impl Drop for ActiveConn {
+    fn drop(&mut self) {
+        self.metrics
+            .inner
+            .connections_active
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+fn load(value: &AtomicU64) -> u64 {
+    value.load(Ordering::Relaxed)
+}
@@ -0,0 +1,328 @@
+use super::*;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::TcpStream,
+};
+
+/// Signature of a version 2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Maximum length of a version 1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// Whether connections must start with a PROXY protocol header, see [Builder::proxy_protocol].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocol {
+    /// A header is parsed if present, connections without one are accepted as well.
+    Allow,
+    /// Connections without a valid header are rejected.
+    Require,
+}
+
+/// A PROXY protocol (version 1 or 2) header, sent by a load balancer ahead of the proxied connection.
+///
+/// See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>
+#[derive(Debug, Clone, Default)]
+pub struct ProxyHeader {
+    /// Address of the client. `None` for health checks (`LOCAL` command) and unknown address families.
+    pub source: Option<SocketAddr>,
+    /// Address that the client connected to.
+    pub destination: Option<SocketAddr>,
+    /// Type-length-value extensions of a version 2 header.
+    pub tlvs: Vec<Tlv>,
+}
+
+/// A type-length-value extension of a version 2 [ProxyHeader].
+#[derive(Debug, Clone)]
+pub struct Tlv {
+    /// Type of the extension, for example `0x01` for ALPN or `0x05` for a unique connection ID.
+    pub kind: u8,
+    /// Value of the extension.
+    pub value: Bytes,
+}
+
+impl ProxyHeader {
+    /// Reads a version 1 or 2 header from `reader`.
+    ///
+    /// It reads exactly the bytes of the header, so the rest of the stream (for example the TLS handshake)
+    /// is left untouched. Fails with [io::ErrorKind::InvalidData] if the header is invalid.
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Self> {
+        let mut buf = [0; 16];
+        reader.read_exact(&mut buf[..1]).await?;
+        match buf[0] {
+            b'P' => {
+                // The shortest header is `PROXY UNKNOWN\r\n`.
+                reader.read_exact(&mut buf[1..15]).await?;
+                let mut line = buf[..15].to_vec();
+                while !line.ends_with(b"\r\n") {
+                    if line.len() >= V1_MAX_LEN {
+                        return Err(invalid());
+                    }
+                    line.push(reader.read_u8().await?);
+                }
+                parse_v1(&line[..line.len() - 2]).ok_or_else(invalid)
+            }
+            b'\r' => {
+                reader.read_exact(&mut buf[1..]).await?;
+                if buf[..12] != V2_SIGNATURE {
+                    return Err(invalid());
+                }
+                let len = u16::from_be_bytes([buf[14], buf[15]]);
+                let mut body = vec![0; usize::from(len)];
+                reader.read_exact(&mut body).await?;
+                parse_v2(buf[12], buf[13], Bytes::from(body)).ok_or_else(invalid)
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns the value of the first extension of type `kind`.
+    pub fn tlv(&self, kind: u8) -> Option<&Bytes> {
+        self.tlvs
+            .iter()
+            .find(|tlv| tlv.kind == kind)
+            .map(|tlv| &tlv.value)
+    }
+}
+
+/// Reads the header of a newly accepted connection.
+///
+/// In [ProxyProtocol::Allow] mode, the first byte is peeked to tell whether a header is present,
+/// as neither a PROXY header nor a TLS handshake can start like the other.
+pub(crate) async fn accept(
+    stream: &mut TcpStream,
+    mode: ProxyProtocol,
+) -> io::Result<Option<ProxyHeader>> {
+    if mode == ProxyProtocol::Allow {
+        let mut first = [0; 1];
+        if stream.peek(&mut first).await? == 0 || !matches!(first[0], b'P' | b'\r') {
+            return Ok(None);
+        }
+    }
+    ProxyHeader::read(stream).await.map(Some)
+}
+
+/// Parses `PROXY <TCP4|TCP6|UNKNOWN> <src ip> <dst ip> <src port> <dst port>`, without CRLF.
+fn parse_v1(line: &[u8]) -> Option<ProxyHeader> {
+    let line = str::from_utf8(line).ok()?;
+    let mut parts = line.split(' ');
+    if parts.next()? != "PROXY" {
+        return None;
+    }
+    let family = parts.next()?;
+    if family == "UNKNOWN" {
+        return Some(ProxyHeader::default());
+    }
+    let (src, dst) = (parts.next()?, parts.next()?);
+    let (src_port, dst_port) = (parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let (src, dst): (IpAddr, IpAddr) = match family {
+        "TCP4" => (
+            src.parse::<Ipv4Addr>().ok()?.into(),
+            dst.parse::<Ipv4Addr>().ok()?.into(),
+        ),
+        "TCP6" => (
+            src.parse::<Ipv6Addr>().ok()?.into(),
+            dst.parse::<Ipv6Addr>().ok()?.into(),
+        ),
+        _ => return None,
+    };
+    Some(ProxyHeader {
+        source: Some(SocketAddr::new(src, src_port.parse().ok()?)),
+        destination: Some(SocketAddr::new(dst, dst_port.parse().ok()?)),
+        tlvs: Vec::new(),
+    })
+}
+
+fn parse_v2(version_command: u8, family: u8, mut body: Bytes) -> Option<ProxyHeader> {
+    if version_command >> 4 != 2 {
+        return None;
+    }
+    let is_local = match version_command & 0x0F {
+        0 => true,
+        1 => false,
+        _ => return None,
+    };
+    let addrs_len = match family >> 4 {
+        0 => 0,
+        1 => 12,
+        2 => 36,
+        3 => 216,
+        _ => return None,
+    };
+    if body.len() < addrs_len {
+        return None;
+    }
+    let addrs = body.split_to(addrs_len);
+    let (source, destination) = match family >> 4 {
+        1 if !is_local => {
+            let ip = |i: usize| IpAddr::from(<[u8; 4]>::try_from(&addrs[i..i + 4]).unwrap());
+            let port = |i: usize| u16::from_be_bytes([addrs[i], addrs[i + 1]]);
+            (
+                Some(SocketAddr::new(ip(0), port(8))),
+                Some(SocketAddr::new(ip(4), port(10))),
+            )
+        }
+        2 if !is_local => {
+            let ip = |i: usize| IpAddr::from(<[u8; 16]>::try_from(&addrs[i..i + 16]).unwrap());
+            let port = |i: usize| u16::from_be_bytes([addrs[i], addrs[i + 1]]);
+            (
+                Some(SocketAddr::new(ip(0), port(32))),
+                Some(SocketAddr::new(ip(16), port(34))),
+            )
+        }
+        _ => (None, None),
+    };
+    let mut tlvs = Vec::new();
+    while !body.is_empty() {
+        if body.len() < 3 {
+            return None;
+        }
+        let kind = body[0];
+        let len = usize::from(u16::from_be_bytes([body[1], body[2]]));
+        if body.len() < 3 + len {
+            return None;
+        }
+        let mut tlv = body.split_to(3 + len);
+        tlvs.push(Tlv {
+            kind,
+            value: tlv.split_off(3),
+        });
+    }
+    Some(ProxyHeader {
+        source,
+        destination,
+        tlvs,
+    })
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid PROXY protocol header")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2(command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20 | command, family]);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    async fn read(mut bytes: &[u8]) -> io::Result<ProxyHeader> {
+        ProxyHeader::read(&mut bytes).await
+    }
+
+    #[test]
+    fn v1() {
+        let header = parse_v1(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443").unwrap();
+        assert_eq!(header.source, Some("192.168.0.1:56324".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("192.168.0.11:443".parse().unwrap())
+        );
+
+        let header = parse_v1(b"PROXY TCP6 2001:db8::1 2001:db8::2 1234 443").unwrap();
+        assert_eq!(header.source, Some("[2001:db8::1]:1234".parse().unwrap()));
+
+        let header = parse_v1(b"PROXY UNKNOWN").unwrap();
+        assert_eq!(header.source, None);
+        assert!(parse_v1(b"PROXY UNKNOWN ignored 1.2.3.4").is_some());
+    }
+
+    #[test]
+    fn invalid_v1() {
+        assert!(parse_v1(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324").is_none());
+        assert!(parse_v1(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443 1").is_none());
+        assert!(parse_v1(b"PROXY TCP4 2001:db8::1 192.168.0.11 56324 443").is_none());
+        assert!(parse_v1(b"PROXY TCP6 192.168.0.1 192.168.0.11 56324 443").is_none());
+        assert!(parse_v1(b"PROXY TCP4 192.168.0.1 192.168.0.11 65536 443").is_none());
+        assert!(parse_v1(b"PROXY UDP4 192.168.0.1 192.168.0.11 56324 443").is_none());
+        assert!(parse_v1(b"proxy TCP4 192.168.0.1 192.168.0.11 56324 443").is_none());
+    }
+
+    #[test]
+    fn v2_addresses() {
+        let body = [127, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90, 0x01, 0xBB];
+        let header = parse_v2(0x21, 0x11, Bytes::copy_from_slice(&body)).unwrap();
+        assert_eq!(header.source, Some("127.0.0.1:8080".parse().unwrap()));
+        assert_eq!(header.destination, Some("10.0.0.1:443".parse().unwrap()));
+
+        let mut body = [0; 36];
+        body[15] = 1;
+        body[31] = 2;
+        body[32..].copy_from_slice(&[0x1F, 0x90, 0x01, 0xBB]);
+        let header = parse_v2(0x21, 0x21, Bytes::copy_from_slice(&body)).unwrap();
+        assert_eq!(header.source, Some("[::1]:8080".parse().unwrap()));
+        assert_eq!(header.destination, Some("[::2]:443".parse().unwrap()));
+    }
+
+    #[test]
+    fn v2_local_and_tlvs() {
+        let mut body = vec![127, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90, 0x01, 0xBB];
+        body.extend_from_slice(&[0x01, 0, 2, b'h', b'2', 0x05, 0, 0]);
+        let header = parse_v2(0x20, 0x11, Bytes::from(body)).unwrap();
+        assert_eq!(header.source, None);
+        assert_eq!(header.tlv(0x01).map(|v| &v[..]), Some(&b"h2"[..]));
+        assert_eq!(header.tlv(0x05).map(|v| v.len()), Some(0));
+        assert!(header.tlv(0x02).is_none());
+    }
+
+    #[test]
+    fn invalid_v2() {
+        // Version 1 in a version 2 header.
+        assert!(parse_v2(0x11, 0x11, Bytes::from(vec![0; 12])).is_none());
+        // Unknown command.
+        assert!(parse_v2(0x22, 0x11, Bytes::from(vec![0; 12])).is_none());
+        // Unknown family.
+        assert!(parse_v2(0x21, 0x41, Bytes::new()).is_none());
+        // Truncated addresses.
+        assert!(parse_v2(0x21, 0x11, Bytes::from(vec![0; 11])).is_none());
+        // Truncated TLV.
+        let mut body = vec![0; 12];
+        body.extend_from_slice(&[0x01, 0, 5, b'h']);
+        assert!(parse_v2(0x21, 0x11, Bytes::from(body)).is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_only_the_header() {
+        let mut stream = &b"PROXY TCP4 1.2.3.4 5.6.7.8 1 2\r\n\x16\x03"[..];
+        let header = ProxyHeader::read(&mut stream).await.unwrap();
+        assert_eq!(header.source, Some("1.2.3.4:1".parse().unwrap()));
+        assert_eq!(stream, b"\x16\x03");
+
+        let mut bytes = v2(1, 0x11, &[1, 2, 3, 4, 5, 6, 7, 8, 0, 1, 0, 2]);
+        bytes.extend_from_slice(b"\x16\x03");
+        let mut stream = &bytes[..];
+        let header = ProxyHeader::read(&mut stream).await.unwrap();
+        assert_eq!(header.destination, Some("5.6.7.8:2".parse().unwrap()));
+        assert_eq!(stream, b"\x16\x03");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_headers() {
+        let too_long = format!("PROXY TCP4 {}\r\n", "1".repeat(V1_MAX_LEN));
+        for bytes in [
+            &b"GET / HTTP/1.1\r\n"[..],
+            b"PROXY TCP4 1.2.3.4\r\n",
+            too_long.as_bytes(),
+            b"\r\n\r\n\0\r\nQUIT!\x21\x11\x00\x00",
+        ] {
+            let err = read(bytes).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        // The header is incomplete.
+        let err = read(b"PROXY TCP4 1.2.3.4 5.6.7.8 1 2").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,306 @@
+use super::*;
+use http::{
+    header::{self, HeaderValue},
+    HeaderMap, Method, StatusCode,
+};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::Range,
+};
+
+/// Requests with more ranges than this are served in full.
+const MAX_RANGES: usize = 16;
+
+/// A response body that can be sent in parts.
+pub(crate) trait RangeBody {
+    /// Sends the bytes of the body within `range`.
+    fn write_range(
+        &mut self,
+        responder: &mut Responder,
+        range: Range<u64>,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl RangeBody for Bytes {
+    async fn write_range(&mut self, responder: &mut Responder, range: Range<u64>) -> Result<()> {
+        responder
+            .write(self.slice(range.start as usize..range.end as usize))
+            .await
+    }
+}
+
+enum Ranges {
+    Full,
+    Partial(Vec<Range<u64>>),
+    Unsatisfiable,
+}
+
+impl Response {
+    /// Sends `bytes` as response body, honoring the `range` and `if-range` request headers.
+    ///
+    /// - A single range is sent as `206 Partial Content` with a `content-range` header.
+    /// - Multiple ranges are sent as a `multipart/byteranges` body.
+    /// - If none of the ranges can be satisfied, `416 Range Not Satisfiable` is sent.
+    ///
+    /// `if-range` is validated against the `etag` and `last-modified` headers of this response,
+    /// so they should be set before calling this method.
+    #[inline]
+    pub async fn write_ranged(self, req: &Request, bytes: impl Into<Bytes>) -> Result<()> {
+        let bytes = bytes.into();
+        let len = bytes.len() as u64;
+        write_ranges(self, req, bytes, len).await
+    }
+}
+
+/// Sends the requested ranges of a body of `len` bytes.
+pub(crate) async fn write_ranges(
+    mut res: Response,
+    req: &Request,
+    mut body: impl RangeBody,
+    len: u64,
+) -> Result<()> {
+    res.headers
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let ranges = if req.method == Method::GET {
+        ranges(&req.headers, &res.headers, len)
+    } else {
+        Ranges::Full
+    };
+    match ranges {
+        Ranges::Full => {
+            res.headers
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+            if req.method == Method::HEAD || len == 0 {
+                return res.send_headers();
+            }
+            let mut responder = res.send_stream()?;
+            body.write_range(&mut responder, 0..len).await?;
+            responder.end()
+        }
+        Ranges::Unsatisfiable => {
+            res.status = StatusCode::RANGE_NOT_SATISFIABLE;
+            res.headers
+                .insert(header::CONTENT_RANGE, content_range(None, len));
+            res.headers
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+            res.send_headers()
+        }
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            res.status = StatusCode::PARTIAL_CONTENT;
+            res.headers
+                .insert(header::CONTENT_RANGE, content_range(Some(&range), len));
+            res.headers.insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(range.end - range.start),
+            );
+            let mut responder = res.send_stream()?;
+            body.write_range(&mut responder, range).await?;
+            responder.end()
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = format!("{:016x}", RandomState::new().build_hasher().finish());
+            let content_type = res.headers.remove(header::CONTENT_TYPE);
+            let part_headers: Vec<Bytes> = ranges
+                .iter()
+                .map(|range| {
+                    let mut head = format!("\r\n--{boundary}\r\n");
+                    if let Some(ty) = content_type.as_ref().and_then(|v| v.to_str().ok()) {
+                        head += &format!("content-type: {ty}\r\n");
+                    }
+                    let range = content_range(Some(range), len);
+                    head += &format!("content-range: {}\r\n\r\n", range.to_str().unwrap());
+                    Bytes::from(head)
+                })
+                .collect();
+
+            let end = Bytes::from(format!("\r\n--{boundary}--\r\n"));
+            let content_length = ranges.iter().map(|r| r.end - r.start).sum::<u64>()
+                + part_headers.iter().map(|h| h.len() as u64).sum::<u64>()
+                + end.len() as u64;
+
+            res.status = StatusCode::PARTIAL_CONTENT;
+            res.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(&format!("multipart/byteranges; boundary={boundary}"))
+                    .unwrap(),
+            );
+            res.headers
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+
+            let mut responder = res.send_stream()?;
+            for (range, head) in ranges.into_iter().zip(part_headers) {
+                responder.write(head).await?;
+                body.write_range(&mut responder, range).await?;
+            }
+            responder.end_write(end).await
+        }
+    }
+}
+
+fn content_range(range: Option<&Range<u64>>, len: u64) -> HeaderValue {
+    let value = match range {
+        Some(range) => format!("bytes {}-{}/{len}", range.start, range.end - 1),
+        None => format!("bytes */{len}"),
+    };
+    HeaderValue::from_str(&value).unwrap()
+}
+
+fn ranges(req: &HeaderMap, res: &HeaderMap, len: u64) -> Ranges {
+    let Some(range) = req.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return Ranges::Full;
+    };
+    if let Some(if_range) = req.get(header::IF_RANGE) {
+        if !if_range_matches(if_range, res) {
+            return Ranges::Full;
+        }
+    }
+    match parse_range(range, len) {
+        None => Ranges::Full,
+        Some(ranges) if ranges.is_empty() => Ranges::Unsatisfiable,
+        Some(ranges) => Ranges::Partial(ranges),
+    }
+}
+
+/// `if-range` contains either an entity tag, which must strongly match the `etag`,
+/// or a date, which must exactly match the `last-modified` date.
+fn if_range_matches(if_range: &HeaderValue, res: &HeaderMap) -> bool {
+    let value = if_range.as_bytes();
+    if value.starts_with(b"\"") || value.starts_with(b"W/") {
+        return !value.starts_with(b"W/")
+            && res
+                .get(header::ETAG)
+                .is_some_and(|etag| etag.as_bytes() == value);
+    }
+    res.get(header::LAST_MODIFIED)
+        .is_some_and(|modified| modified.as_bytes() == value)
+}
+
+/// Parses a `bytes=...` range header for a body of `len` bytes.
+///
+/// Returns `None` if the header is invalid and should be ignored,
+/// or the list of satisfiable ranges (which may be empty).
+///
+/// Overlapping or adjacent ranges are merged, so each byte is sent at most once.
+fn parse_range(value: &str, len: u64) -> Option<Vec<Range<u64>>> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+    let mut ranges = Vec::new();
+    for (i, spec) in specs.split(',').map(str::trim).enumerate() {
+        if i >= MAX_RANGES {
+            return None;
+        }
+        let (start, end) = spec.split_once('-')?;
+        let range = if start.is_empty() {
+            let suffix = end.parse::<u64>().ok()?;
+            len.saturating_sub(suffix)..len
+        } else {
+            let start = start.parse::<u64>().ok()?;
+            let end = match end {
+                "" => len,
+                end => {
+                    let end = end.parse::<u64>().ok()?;
+                    if end < start {
+                        return None;
+                    }
+                    end.saturating_add(1).min(len)
+                }
+            };
+            start..end
+        };
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+    ranges.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Some(merged)
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(parse_range("bytes=0-499", 1000), Some(vec![0..500]));
+        assert_eq!(parse_range("bytes=500-", 1000), Some(vec![500..1000]));
+        assert_eq!(parse_range("bytes=-200", 1000), Some(vec![800..1000]));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some(vec![900..1000]));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some(vec![0..1000]));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(parse_range("bytes=-0", 1000), Some(vec![]));
+        assert_eq!(parse_range("bytes=0-0", 0), Some(vec![]));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!(parse_range("items=0-1", 1000), None);
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=10", 1000), None);
+        assert_eq!(
+            parse_range(&format!("bytes={}", ["0-1"; 17].join(",")), 1000),
+            None
+        );
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        let repeated = format!("bytes={}", ["0-"; 16].join(","));
+        assert_eq!(parse_range(&repeated, 1 << 20), Some(vec![0..1 << 20]));
+        assert_eq!(
+            parse_range("bytes=500-599, 0-99, 100-199, 550-700", 1000),
+            Some(vec![0..200, 500..701])
+        );
+        assert_eq!(
+            parse_range("bytes=0-9, 20-29, -5", 100),
+            Some(vec![0..10, 20..30, 95..100])
+        );
+    }
+
+    #[test]
+    fn if_range() {
+        let mut res = HeaderMap::new();
+        res.insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        res.insert(header::LAST_MODIFIED, HeaderValue::from_static(date));
+
+        assert!(if_range_matches(&HeaderValue::from_static("\"v1\""), &res));
+        assert!(!if_range_matches(&HeaderValue::from_static("\"v2\""), &res));
+        assert!(!if_range_matches(
+            &HeaderValue::from_static("W/\"v1\""),
+            &res
+        ));
+        assert!(if_range_matches(&HeaderValue::from_static(date), &res));
+        assert!(!if_range_matches(
+            &HeaderValue::from_static("Thu, 22 Oct 2015 07:28:00 GMT"),
+            &res
+        ));
+    }
+
+    #[test]
+    fn if_range_mismatch_serves_full_body() {
+        let mut req = HeaderMap::new();
+        req.insert(header::RANGE, HeaderValue::from_static("bytes=0-9"));
+        req.insert(header::IF_RANGE, HeaderValue::from_static("\"old\""));
+        let mut res = HeaderMap::new();
+        res.insert(header::ETAG, HeaderValue::from_static("\"new\""));
+        assert!(matches!(ranges(&req, &res, 100), Ranges::Full));
+
+        res.insert(header::ETAG, HeaderValue::from_static("\"old\""));
+        assert!(matches!(ranges(&req, &res, 100), Ranges::Partial(r) if r == [0..10]));
+    }
+}
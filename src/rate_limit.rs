@@ -0,0 +1,50 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A token bucket that allows `burst` events at once, refilled at `burst` tokens per `period`.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    burst: f64,
+    per_sec: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(burst: u32, period: Duration) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            burst,
+            per_sec: burst / period.as_secs_f64().max(f64::EPSILON),
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.burst);
+        self.last = now;
+    }
+
+    /// Returns `true` if the bucket is refilled up to its burst size.
+    pub(crate) fn is_full(&mut self) -> bool {
+        self.refill(Instant::now());
+        self.tokens >= self.burst
+    }
+
+    /// Returns the time until the next token is available.
+    pub(crate) fn retry_after(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.per_sec)
+    }
+
+    /// Takes a token, returns `false` if the bucket is empty.
+    pub(crate) fn try_acquire(&mut self) -> bool {
+        self.refill(Instant::now());
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
+use crate::{stats::StreamStats, timeout::IdleTimer};
 use http::HeaderMap;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 /// Represents an HTTP request object. It consists of the request headers and body.
 pub struct Request {
@@ -17,6 +19,9 @@ pub struct Request {
 pub struct RecvStream {
     #[doc(hidden)]
     pub inner: h2::RecvStream,
+    pub(crate) timer: IdleTimer,
+    pub(crate) timed_out: bool,
+    pub(crate) stats: Arc<StreamStats>,
 }
 
 impl RecvStream {
@@ -27,10 +32,21 @@ impl RecvStream {
     }
 
     /// Poll for the next data frame.
-    #[inline]
+    ///
+    /// If [Builder::body_read_timeout] elapses while waiting, it fails with `CANCEL`,
+    /// and [RecvStream::is_timed_out] returns `true`.
     pub fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes>>> {
-        self.inner.poll_data(cx).map(|out| match out {
+        let Poll::Ready(out) = self.inner.poll_data(cx) else {
+            if self.timer.poll_elapsed(cx).is_ready() {
+                self.timed_out = true;
+                return Poll::Ready(Some(Err(h2::Error::from(h2::Reason::CANCEL))));
+            }
+            return Poll::Pending;
+        };
+        self.timer.reset();
+        Poll::Ready(match out {
             Some(Ok(data)) => {
+                self.stats.add_bytes_received(data.len());
                 let data = self
                     .inner
                     .flow_control()
@@ -43,6 +59,12 @@ impl RecvStream {
         })
     }
 
+    /// Returns `true` if reading the body failed because [Builder::body_read_timeout] elapsed.
+    #[inline]
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out
+    }
+
     /// Returns the stream ID of this stream.
     ///
     /// # Panics
@@ -69,6 +91,24 @@ impl RecvStream {
     }
 }
 
+/// Address of the client, stored in the request extensions.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PeerAddr(pub(crate) SocketAddr);
+
+impl Request {
+    /// Returns the address of the client, if the connection was accepted by a [Server].
+    #[inline]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.head.extensions.get::<PeerAddr>().map(|peer| peer.0)
+    }
+
+    /// Returns the current round-trip time of the connection, see [Builder::keep_alive_interval].
+    #[inline]
+    pub fn rtt(&self) -> Option<Duration> {
+        self.head.extensions.get::<Rtt>()?.get()
+    }
+}
+
 impl std::fmt::Debug for Request {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("Request")?;
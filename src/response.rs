@@ -1,5 +1,20 @@
 use super::*;
+use crate::{
+    budget::{ConnBudget, StreamBudget},
+    flood::ActiveStream,
+    stats::StreamStats,
+    timeout::{self, IdleTimer},
+};
 use h2::{server::SendResponse, SendStream, StreamId};
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+    HeaderValue, StatusCode,
+};
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    task::ready,
+    time::Duration,
+};
 
 /// Represents an HTTP response object.
 #[derive(Debug)]
@@ -11,6 +26,11 @@ pub struct Response {
     /// Responsible for sending the HTTP response body
     #[doc(hidden)]
     pub sender: SendResponse<Bytes>,
+    pub(crate) budget: Option<Arc<ConnBudget>>,
+    pub(crate) write_timeout: Option<Duration>,
+    pub(crate) expired: Option<Arc<AtomicBool>>,
+    pub(crate) stream: ActiveStream,
+    pub(crate) stats: Arc<StreamStats>,
 }
 
 impl Response {
@@ -27,7 +47,8 @@ impl Response {
     fn create_response(mut self, end: bool) -> Result<SendStream<Bytes>> {
         let mut response = http::Response::new(());
         *response.status_mut() = self.status;
-        *response.headers_mut() = self.headers;
+        *response.headers_mut() = std::mem::take(&mut self.headers);
+        self.stats.set_status(self.status);
         self.sender.send_response(response, end)
     }
 
@@ -41,8 +62,19 @@ impl Response {
     /// This method is used to obtain a [Responder] that can be used to send the response body.
     #[inline]
     pub fn send_stream(self) -> Result<Responder> {
+        let budget = self.budget.as_ref().map(ConnBudget::stream);
+        let timer = IdleTimer::new(self.write_timeout);
+        let stream = self.stream.clone();
+        let stats = Arc::clone(&self.stats);
         let inner = self.create_response(false)?;
-        Ok(Responder { inner })
+        Ok(Responder {
+            inner,
+            budget,
+            timer,
+            _stream: stream,
+            stats,
+            is_stalled: false,
+        })
     }
 
     /// Sends response data to the remote peer.
@@ -54,13 +86,94 @@ impl Response {
     /// The data is buffered and the capacity is implicitly requested. Once the
     /// capacity becomes available, the data is flushed to the connection.
     ///
-    /// However, this buffering is unbounded. As such, sending large amounts of
+    /// By default, this buffering is unbounded. As such, sending large amounts of
     /// data without reserving capacity before hand could result in large
     /// amounts of data being buffered in memory.
+    /// [Builder::max_buffered_per_stream] and [Builder::max_buffered_per_conn] bound it,
+    /// in which case it fails with `ENHANCE_YOUR_CALM` once the limit is exceeded.
     #[inline]
     pub fn write_unbound(self, bytes: impl Into<Bytes>) -> Result<()> {
         self.send_stream()?.end_write_unbound(bytes)
     }
+
+    /// Sends the body with the given `content-type` and `content-length` headers.
+    pub async fn write_typed(
+        mut self,
+        content_type: &'static str,
+        bytes: impl Into<Bytes>,
+    ) -> Result<()> {
+        let bytes = bytes.into();
+        self.headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        self.headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+        self.write(bytes).await
+    }
+
+    /// Sends a `text/plain` response.
+    #[inline]
+    pub async fn text(self, body: impl Into<Bytes>) -> Result<()> {
+        self.write_typed("text/plain; charset=utf-8", body).await
+    }
+
+    /// Sends a `text/html` response.
+    #[inline]
+    pub async fn html(self, body: impl Into<Bytes>) -> Result<()> {
+        self.write_typed("text/html; charset=utf-8", body).await
+    }
+
+    /// Serializes `value` and sends it as an `application/json` response.
+    ///
+    /// If serialization fails, a `500 Internal Server Error` is sent instead.
+    #[cfg(feature = "json")]
+    pub async fn json<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        match serde_json::to_vec(value) {
+            Ok(body) => self.write_typed("application/json", body).await,
+            Err(err) => {
+                self.error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+                    .await
+            }
+        }
+    }
+
+    /// Sends a redirect response to `location`.
+    ///
+    /// `status` should be one of the `3xx` status codes, such as
+    /// [StatusCode::FOUND], [StatusCode::SEE_OTHER] or [StatusCode::PERMANENT_REDIRECT].
+    ///
+    /// Fails with `INTERNAL_ERROR` if `location` is not a valid header value.
+    pub fn redirect(mut self, status: StatusCode, location: &str) -> Result<()> {
+        debug_assert!(status.is_redirection(), "{status} is not a redirection");
+        let location = HeaderValue::from_str(location)
+            .map_err(|_| h2::Error::from(h2::Reason::INTERNAL_ERROR))?;
+
+        self.status = status;
+        self.headers.insert(LOCATION, location);
+        self.headers.insert(CONTENT_LENGTH, HeaderValue::from(0));
+        self.send_headers()
+    }
+
+    /// Sends an error response with the given `status` code and a plain text message.
+    #[inline]
+    pub async fn error(mut self, status: StatusCode, msg: impl Into<Bytes>) -> Result<()> {
+        self.status = status;
+        self.text(msg).await
+    }
+}
+
+impl Drop for Response {
+    fn drop(&mut self) {
+        if timeout::is_expired(&self.expired) {
+            let mut response = http::Response::new(());
+            *response.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+            response
+                .headers_mut()
+                .insert(CONTENT_LENGTH, HeaderValue::from(0));
+            if self.sender.send_response(response, true).is_ok() {
+                self.stats.set_status(StatusCode::GATEWAY_TIMEOUT);
+            }
+        }
+    }
 }
 
 /// The [Responder] struct created from `Response::send_stream`
@@ -69,25 +182,55 @@ impl Response {
 pub struct Responder {
     #[doc(hidden)]
     pub inner: SendStream<Bytes>,
+    budget: Option<StreamBudget>,
+    timer: IdleTimer,
+    /// Counts the stream as active, see [Conn::num_active_streams].
+    _stream: ActiveStream,
+    stats: Arc<StreamStats>,
+    is_stalled: bool,
 }
 
 impl Responder {
+    /// Requests `len` bytes of send capacity and polls until some capacity is assigned.
+    ///
+    /// Returns the number of bytes that can be sent, which is at most `len`.
+    pub(crate) fn poll_reserve(&mut self, cx: &mut Context<'_>, len: usize) -> Poll<Result<usize>> {
+        self.inner.reserve_capacity(len);
+        loop {
+            let capacity = self.inner.capacity();
+            if capacity > 0 {
+                self.timer.reset();
+                self.is_stalled = false;
+                return Poll::Ready(Ok(capacity.min(len)));
+            }
+            let Poll::Ready(capacity) = self.inner.poll_capacity(cx) else {
+                if !self.is_stalled {
+                    self.is_stalled = true;
+                    self.stats.on_flow_control_stall();
+                }
+                ready!(self.timer.poll_elapsed(cx));
+                debug!("write idle timeout elapsed, resetting the stream");
+                self.inner.send_reset(h2::Reason::CANCEL);
+                return Poll::Ready(Err(h2::Error::from(h2::Reason::CANCEL)));
+            };
+            match capacity {
+                None => return Poll::Ready(Err(h2::Error::from(h2::Reason::CANCEL))),
+                Some(Err(err)) => return Poll::Ready(Err(err)),
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+
     /// Make sure `bytes` is **Not Empty**
     #[doc(hidden)]
     pub async fn write_bytes(&mut self, mut bytes: Bytes, end: bool) -> Result<()> {
         loop {
             let len = bytes.len();
-            self.inner.reserve_capacity(len);
-            match poll_fn(|cx| self.inner.poll_capacity(cx)).await {
-                None => return Err(h2::Error::from(h2::Reason::CANCEL)),
-                Some(nbytes) => {
-                    let nbytes = nbytes?;
-                    if len <= nbytes {
-                        return self.inner.send_data(bytes, end);
-                    }
-                    self.inner.send_data(bytes.split_to(nbytes), false)?;
-                }
-            };
+            let nbytes = poll_fn(|cx| self.poll_reserve(cx, len)).await?;
+            if len <= nbytes {
+                return self.send_data(bytes, end);
+            }
+            self.send_data(bytes.split_to(nbytes), false)?;
         }
     }
 
@@ -112,21 +255,40 @@ impl Responder {
     /// The data is buffered and the capacity is implicitly requested. Once the
     /// capacity becomes available, the data is flushed to the connection.
     ///
-    /// However, this buffering is unbounded. As such, sending large amounts of
+    /// By default, this buffering is unbounded. As such, sending large amounts of
     /// data without reserving capacity before hand could result in large
     /// amounts of data being buffered in memory.
+    /// [Builder::max_buffered_per_stream] and [Builder::max_buffered_per_conn] bound it,
+    /// in which case it fails with `ENHANCE_YOUR_CALM` once the limit is exceeded.
     pub fn write_unbound(&mut self, bytes: impl Into<Bytes>) -> Result<()> {
-        self.inner.send_data(bytes.into(), false)
+        let bytes = self.track(bytes.into())?;
+        self.send_data(bytes, false)
     }
 
     /// Sends final chunk of data to the remote peer.
     pub fn end_write_unbound(mut self, bytes: impl Into<Bytes>) -> Result<()> {
-        self.inner.send_data(bytes.into(), true)
+        let bytes = self.track(bytes.into())?;
+        self.send_data(bytes, true)
+    }
+
+    /// Sends a data frame, and counts it in the statistics of the response.
+    pub(crate) fn send_data(&mut self, bytes: Bytes, end: bool) -> Result<()> {
+        let len = bytes.len();
+        self.inner.send_data(bytes, end)?;
+        self.stats.add_bytes_sent(len);
+        Ok(())
+    }
+
+    fn track(&self, bytes: Bytes) -> Result<Bytes> {
+        match &self.budget {
+            Some(budget) => budget.track(bytes),
+            None => Ok(bytes),
+        }
     }
 
     /// Signals the end of writing the response body.
     #[inline]
     pub fn end(mut self) -> Result<()> {
-        self.inner.send_data(Bytes::new(), true)
+        self.send_data(Bytes::new(), true)
     }
 }
@@ -0,0 +1,290 @@
+use super::*;
+use http::{
+    header::{self, HeaderValue},
+    HeaderMap, Method, StatusCode,
+};
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::fs::File;
+
+/// A service that serves files from a directory.
+///
+/// - Only `GET` and `HEAD` requests are allowed.
+/// - Request paths are percent-decoded, and paths escaping the root directory are rejected.
+/// - Directory requests are served with the index file (`index.html` by default).
+/// - Responses carry `content-type`, `etag` and `last-modified` headers, and
+///   conditional requests (`if-none-match`, `if-modified-since`) are answered with `304 Not Modified`.
+/// - Byte-range requests (`range`, `if-range`) are supported, only the requested parts of the file are read.
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+///
+/// #[tokio::main]
+/// async fn main() -> std::io::Result<()> {
+///     let conf = Server::config("examples/key.pem", "examples/cert.pem")?;
+///     let server = Server::bind("127.0.0.1:4433", conf).await?;
+///     let public = ServeDir::new("public");
+///     loop {
+///         if let Ok((conn, _)) = server.accept().await {
+///             conn.incoming(public.clone());
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServeDir {
+    root: Arc<Path>,
+    index: Arc<str>,
+}
+
+/// A service that serves a single file, regardless of the request path.
+///
+/// It has the same behavior as [ServeDir] regarding methods, headers and conditional requests.
+#[derive(Debug, Clone)]
+pub struct ServeFile {
+    path: Arc<Path>,
+}
+
+impl ServeDir {
+    /// Creates a new [ServeDir] serving files from the `root` directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into().into(),
+            index: "index.html".into(),
+        }
+    }
+
+    /// Sets the file served for directory requests. Default: `index.html`
+    pub fn index_file(mut self, name: &str) -> Self {
+        self.index = name.into();
+        self
+    }
+
+    /// Serves the file matching the request path.
+    pub async fn serve(&self, req: &Request, res: Response) -> Result<()> {
+        if !is_get_or_head(&req.method) {
+            return method_not_allowed(res).await;
+        }
+        let path = req.uri.path();
+        let Some(mut file_path) = resolve(&self.root, path) else {
+            return res.error(StatusCode::NOT_FOUND, "Not Found").await;
+        };
+        if tokio::fs::metadata(&file_path)
+            .await
+            .is_ok_and(|m| m.is_dir())
+        {
+            if !path.ends_with('/') {
+                let location = dir_location(path, req.uri.query());
+                return res.redirect(StatusCode::MOVED_PERMANENTLY, &location);
+            }
+            file_path.push(&*self.index);
+        }
+        serve_file(&file_path, req, res).await
+    }
+}
+
+impl ServeFile {
+    /// Creates a new [ServeFile] serving the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into().into(),
+        }
+    }
+
+    /// Serves the file.
+    pub async fn serve(&self, req: &Request, res: Response) -> Result<()> {
+        if !is_get_or_head(&req.method) {
+            return method_not_allowed(res).await;
+        }
+        serve_file(&self.path, req, res).await
+    }
+}
+
+impl Incoming for ServeDir {
+    async fn stream(self, req: Request, res: Response) {
+        let _ = self.serve(&req, res).await;
+    }
+}
+
+impl Incoming for ServeFile {
+    async fn stream(self, req: Request, res: Response) {
+        let _ = self.serve(&req, res).await;
+    }
+}
+
+fn is_get_or_head(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD
+}
+
+async fn method_not_allowed(mut res: Response) -> Result<()> {
+    res.headers
+        .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+    res.error(StatusCode::METHOD_NOT_ALLOWED, "Method Not Allowed")
+        .await
+}
+
+/// Maps the request `path` to a file system path within `root`.
+///
+/// Returns `None` if the path is not valid UTF-8 after percent-decoding,
+/// or if it tries to escape the `root` directory.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+
+    let mut file_path = root.to_path_buf();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            _ if segment.contains(['\\', '\0', ':']) => return None,
+            _ => file_path.push(segment),
+        }
+    }
+    Some(file_path)
+}
+
+/// Returns the location of the directory at `path`, with a trailing slash.
+///
+/// It is built from the normalized segments, as a path starting with `//` would redirect to another host.
+fn dir_location(path: &str, query: Option<&str>) -> String {
+    let mut location = String::from("/");
+    for segment in path.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        location += segment;
+        location.push('/');
+    }
+    if let Some(query) = query {
+        location.push('?');
+        location += query;
+    }
+    location
+}
+
+async fn serve_file(path: &Path, req: &Request, mut res: Response) -> Result<()> {
+    let (file, meta) = match open(path).await {
+        Ok(file) => file,
+        Err(err) => {
+            let status = match err.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return res
+                .error(status, status.canonical_reason().unwrap_or_default())
+                .await;
+        }
+    };
+    let modified = meta.modified().ok();
+    let etag = etag(&meta, modified);
+    res.headers.insert(header::ETAG, etag.clone());
+    if let Some(modified) = modified {
+        let value = httpdate::fmt_http_date(modified);
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            res.headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+    if is_not_modified(&req.headers, &etag, modified) {
+        res.status = StatusCode::NOT_MODIFIED;
+        return res.send_headers();
+    }
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    if let Ok(value) = HeaderValue::from_str(mime.as_ref()) {
+        res.headers.insert(header::CONTENT_TYPE, value);
+    }
+    res.write_file(req, FileBody::new(file, meta.len())).await
+}
+
+async fn open(path: &Path) -> io::Result<(File, Metadata)> {
+    let file = File::open(path).await?;
+    let meta = file.metadata().await?;
+    if !meta.is_file() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    Ok((file, meta))
+}
+
+/// Creates a strong entity tag from the file size and modification time.
+fn etag(meta: &Metadata, modified: Option<SystemTime>) -> HeaderValue {
+    let modified = modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    let etag = format!(
+        "\"{:x}-{:x}.{:x}\"",
+        meta.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    );
+    HeaderValue::from_str(&etag).expect("valid etag")
+}
+
+fn is_not_modified(headers: &HeaderMap, etag: &HeaderValue, modified: Option<SystemTime>) -> bool {
+    // `if-none-match` takes precedence over `if-modified-since`
+    if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(value) = value.to_str() else {
+            return false;
+        };
+        let etag = weak(etag.to_str().unwrap_or_default());
+        return value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || weak(tag) == etag);
+    }
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| httpdate::parse_http_date(v.to_str().ok()?).ok());
+
+    match (since, modified) {
+        // HTTP dates have a resolution of one second.
+        (Some(since), Some(modified)) => modified
+            .duration_since(since)
+            .map_or(true, |elapsed| elapsed.as_secs() == 0),
+        _ => false,
+    }
+}
+
+/// Weak comparison of entity tags ignores the `W/` prefix.
+fn weak(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_within_root() {
+        let root = Path::new("/srv");
+        assert_eq!(
+            resolve(root, "/a/b.txt"),
+            Some(PathBuf::from("/srv/a/b.txt"))
+        );
+        assert_eq!(
+            resolve(root, "//a/./b%20c"),
+            Some(PathBuf::from("/srv/a/b c"))
+        );
+        assert_eq!(resolve(root, "/"), Some(PathBuf::from("/srv")));
+        assert_eq!(resolve(root, "/a/../b"), None);
+        assert_eq!(resolve(root, "/a/%2e%2e/b"), None);
+        assert_eq!(resolve(root, "/a%5cb"), None);
+        assert_eq!(resolve(root, "/%ff"), None);
+    }
+
+    #[test]
+    fn directory_location() {
+        assert_eq!(dir_location("/static", None), "/static/");
+        assert_eq!(dir_location("//static", None), "/static/");
+        assert_eq!(
+            dir_location("///evil.com//a/./b", Some("x=1")),
+            "/evil.com/a/b/?x=1"
+        );
+        assert_eq!(dir_location("/a%20b", None), "/a%20b/");
+    }
+}
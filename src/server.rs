@@ -1,5 +1,19 @@
 use super::*;
-use std::{net::SocketAddr, ops, path::Path, sync::Arc};
+use crate::{
+    budget::ConnBudget,
+    flood::{ActiveStream, StreamTracker},
+    handler::PerConn,
+    inspect::{ConnEvent, Events, Inspect},
+    ip_limit::{self, ClientConn, IpLimiter},
+    keep_alive::KeepAlive,
+    metrics::{ActiveConn, HandshakeFailure},
+    proxy_protocol,
+    rate_limit::TokenBucket,
+    request::PeerAddr,
+    stats::StreamStats,
+    timeout::{self, IdleTimer, MaxAge, Stage, Timeouts},
+};
+use std::{net::SocketAddr, ops, path::Path, sync::Arc, time::Duration};
 use tokio::{
     io::{self, AsyncRead, AsyncWrite},
     net::{TcpStream, ToSocketAddrs},
@@ -12,6 +26,8 @@ pub struct Server {
     #[doc(hidden)]
     /// The underlying [TlsListener] instance that provides secure transport layer functionality
     pub listener: TlsListener,
+    pub(crate) builder: Builder,
+    pub(crate) ip_limiter: Option<Arc<IpLimiter>>,
 }
 
 impl Server {
@@ -34,9 +50,13 @@ impl Server {
         addr: impl ToSocketAddrs,
         conf: impl Into<Arc<rustls::ServerConfig>>,
     ) -> io::Result<Self> {
-        Ok(Self {
-            listener: TlsListener::bind(addr, conf).await?,
-        })
+        Builder::new().bind(addr, conf).await
+    }
+
+    /// Creates a [Builder] to configure the server and its connections.
+    #[inline]
+    pub fn builder() -> Builder {
+        Builder::new()
     }
 
     /// This method wraps the current server instance and returns a [GracefulShutdown]
@@ -46,18 +66,123 @@ impl Server {
     }
 
     /// Accept incoming connections
-    #[inline]
+    ///
+    /// With [Builder::proxy_protocol], the returned address is the one of the original client.
+    ///
+    /// The connection is set up before it is returned, see [Builder::handshake_timeout].
     pub async fn accept(&self) -> io::Result<(Conn<TlsStream<TcpStream>>, SocketAddr)> {
-        let (stream, addr) = self.listener.accept_tls().await?;
-        let conn = Conn::handshake(stream).await.map_err(io_err)?;
+        let (mut stream, mut addr) = self.listener.tcp_listener.accept().await?;
+        self.builder.on_accept();
+        let deadline = self.builder.handshake_deadline();
+        let proxy_header = match self.builder.proxy_protocol_mode() {
+            Some(mode) => {
+                match timeout::until(deadline, proxy_protocol::accept(&mut stream, mode)).await {
+                    Some(Ok(header)) => header,
+                    Some(Err(err)) => {
+                        debug!(peer = %addr, %err, "invalid PROXY protocol header");
+                        self.builder
+                            .on_handshake_failure(HandshakeFailure::ProxyProtocol);
+                        return Err(err);
+                    }
+                    None => {
+                        debug!(peer = %addr, "handshake timeout elapsed reading the PROXY protocol header");
+                        return Err(self.handshake_timed_out());
+                    }
+                }
+            }
+            None => None,
+        };
+        if let Some(source) = proxy_header.as_ref().and_then(|header| header.source) {
+            addr = source;
+        }
+        let client = match &self.ip_limiter {
+            Some(limiter) => match limiter.connect(addr.ip()) {
+                Some(client) => Some(client),
+                None => {
+                    debug!(peer = %addr, "too many connections from the client");
+                    self.builder
+                        .on_handshake_failure(HandshakeFailure::TooManyConnections);
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        "too many connections from the client",
+                    ));
+                }
+            },
+            None => None,
+        };
+        let tls = self.listener.tls_acceptor.accept(stream);
+        let stream = match timeout::until(deadline, tls).await {
+            Some(Ok(stream)) => stream,
+            Some(Err(err)) => {
+                debug!(peer = %addr, %err, "TLS handshake failed");
+                self.builder.on_handshake_failure(HandshakeFailure::Tls);
+                return Err(err);
+            }
+            None => {
+                debug!(peer = %addr, "handshake timeout elapsed during the TLS handshake");
+                return Err(self.handshake_timed_out());
+            }
+        };
+        let mut conn = match self.builder.handshake_until(stream, deadline).await {
+            Ok(conn) => conn,
+            Err(err) => {
+                debug!(peer = %addr, %err, "HTTP/2 handshake failed");
+                return Err(io_err(err));
+            }
+        };
+        #[cfg(feature = "tracing")]
+        conn.span.record("peer", tracing::field::display(addr));
+        conn.peer_addr = Some(addr);
+        conn.client = client;
+        conn.proxy_header = proxy_header;
         Ok((conn, addr))
     }
+
+    fn handshake_timed_out(&self) -> io::Error {
+        self.builder.on_handshake_failure(HandshakeFailure::Timeout);
+        io::Error::new(io::ErrorKind::TimedOut, "handshake timeout elapsed")
+    }
+}
+
+/// Information about a new connection, see [Incoming::on_connect].
+#[derive(Debug, Clone)]
+pub struct ConnInfo {
+    /// Address of the client, if the connection was accepted by a [Server].
+    pub peer_addr: Option<SocketAddr>,
+    /// The PROXY protocol header received before the connection, see [Builder::proxy_protocol].
+    pub proxy_header: Option<ProxyHeader>,
+    /// Round-trip time of the connection, see [Builder::keep_alive_interval].
+    pub rtt: Rtt,
 }
 
 /// Represents an HTTP/2 connection.
 #[derive(Debug)]
 pub struct Conn<IO> {
-    inner: h2::server::Connection<IO, Bytes>,
+    pub(crate) inner: h2::server::Connection<Inspect<IO>, Bytes>,
+    pub(crate) budget: Option<Arc<ConnBudget>>,
+    pub(crate) timeouts: Timeouts,
+    pub(crate) keep_alive: Option<KeepAlive>,
+    pub(crate) idle: IdleTimer,
+    pub(crate) max_age: Option<MaxAge>,
+    pub(crate) streams: Arc<StreamTracker>,
+    pub(crate) stream_rate: Option<TokenBucket>,
+    /// Each running handler task holds a reference.
+    pub(crate) handlers: Arc<()>,
+    pub(crate) max_handlers: Option<usize>,
+    pub(crate) is_flooded: bool,
+    /// Reason of the last `GOAWAY` frame sent.
+    pub(crate) goaway_sent: Option<h2::Reason>,
+    /// Protocol events reported to the [Incoming] hooks, see [Builder::connection_events].
+    pub(crate) events: Option<Arc<Events>>,
+    /// The per IP limits of the client, see [Builder::max_requests_per_ip].
+    pub(crate) client: Option<ClientConn>,
+    pub(crate) proxy_header: Option<ProxyHeader>,
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) is_closed: bool,
+    /// Counts the connection as active, see [Builder::metrics].
+    pub(crate) metrics: Option<ActiveConn>,
+    #[cfg(feature = "tracing")]
+    pub(crate) span: tracing::Span,
 }
 
 impl<IO> Conn<IO>
@@ -67,7 +192,7 @@ where
     /// Creates a new configured HTTP/2 server with default configuration.
     #[inline]
     pub async fn handshake(io: IO) -> Result<Conn<IO>> {
-        h2::server::handshake(io).await.map(|inner| Self { inner })
+        Builder::new().handshake(io).await
     }
 
     /// Accept a new incoming stream on the HTTP/2 connection
@@ -75,28 +200,258 @@ where
         poll_fn(|cx| self.poll_accept(cx)).await
     }
 
+    /// Returns the round-trip time of the last `PING` acknowledged by the peer,
+    /// see [Builder::keep_alive_interval]
+    #[inline]
+    pub fn rtt(&self) -> Option<Duration> {
+        self.keep_alive.as_ref()?.rtt().get()
+    }
+
+    /// Returns a shared handle to the round-trip time, which stays up to date while the connection is served.
+    ///
+    /// It is also available from [ConnInfo::rtt] and [Request::rtt].
+    pub fn rtt_handle(&self) -> Rtt {
+        self.keep_alive
+            .as_ref()
+            .map(|keep_alive| keep_alive.rtt().clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the PROXY protocol header received before the connection, see [Builder::proxy_protocol]
+    #[inline]
+    pub fn proxy_header(&self) -> Option<&ProxyHeader> {
+        self.proxy_header.as_ref()
+    }
+
+    /// Returns the address of the client, if the connection was accepted by a [Server].
+    #[inline]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Returns the number of streams with a response in progress.
+    #[inline]
+    pub fn num_active_streams(&self) -> usize {
+        self.streams.num_active()
+    }
+
+    fn poll_liveness(&mut self, cx: &mut Context<'_>) {
+        if !self.is_flooded && self.streams.is_flooded() {
+            self.is_flooded = true;
+            warn!(parent: &self.span, "rate limit exceeded, sending GOAWAY with ENHANCE_YOUR_CALM");
+            self.go_away(Some(h2::Reason::ENHANCE_YOUR_CALM));
+        }
+        if self.streams.take_header_timeout() {
+            debug!(parent: &self.span, "request header timeout elapsed, closing the connection");
+            self.go_away(Some(h2::Reason::NO_ERROR));
+        }
+        if let Some(keep_alive) = &mut self.keep_alive {
+            if keep_alive.poll_expired(cx).is_ready() {
+                // The peer is unresponsive, the connection is dropped without waiting for it.
+                debug!(parent: &self.span, "keep-alive timeout elapsed, closing the connection");
+                self.is_closed = true;
+                return;
+            }
+        }
+        if let Some(max_age) = &mut self.max_age {
+            match max_age.poll_stage(cx) {
+                Poll::Ready(Stage::Draining) => {
+                    debug!(parent: &self.span, "max connection age reached, sending GOAWAY");
+                    self.go_away(None);
+                }
+                Poll::Ready(Stage::Closing) => {
+                    debug!(parent: &self.span, "max connection age grace elapsed, closing the connection");
+                    self.go_away(Some(h2::Reason::NO_ERROR));
+                }
+                _ => {}
+            }
+        }
+        if self.streams.poll_idle(cx).is_pending() {
+            self.idle.reset();
+        } else if self.idle.poll_elapsed(cx).is_ready() {
+            self.idle = IdleTimer::default();
+            debug!(parent: &self.span, "idle timeout elapsed, sending GOAWAY");
+            self.go_away(None);
+        }
+    }
+
     #[doc(hidden)]
     pub fn poll_accept(
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<(Request, Response)>>> {
-        self.inner.poll_accept(cx).map(|event| {
-            event.map(|accept| {
-                accept.map(|(req, sender)| {
-                    let (head, body) = req.into_parts();
-                    let request = Request {
-                        head,
-                        body: RecvStream { inner: body },
-                    };
-                    let response = Response {
-                        status: http::StatusCode::default(),
-                        headers: http::HeaderMap::default(),
-                        sender,
-                    };
-                    (request, response)
-                })
-            })
+        loop {
+            self.poll_liveness(cx);
+            if self.is_closed {
+                return Poll::Ready(None);
+            }
+            let (req, mut sender) = match self.inner.poll_accept(cx) {
+                // Resets received meanwhile may have exceeded the limit.
+                Poll::Pending if !self.is_flooded && self.streams.is_flooded() => continue,
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(accept))) => accept,
+                Poll::Ready(Some(Err(err))) => {
+                    if err.is_go_away() && err.is_remote() {
+                        debug!(parent: &self.span, reason = ?err.reason(), "received GOAWAY");
+                    } else {
+                        debug!(parent: &self.span, %err, "connection error");
+                    }
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => {
+                    debug!(parent: &self.span, "connection closed");
+                    return Poll::Ready(None);
+                }
+            };
+            if let Some(stream_rate) = &mut self.stream_rate {
+                if !stream_rate.try_acquire() {
+                    warn!(parent: &self.span, "stream rate exceeded, refusing the stream");
+                    sender.send_reset(h2::Reason::REFUSED_STREAM);
+                    self.streams.flood();
+                    continue;
+                }
+            }
+            if let Some(client) = &self.client {
+                if let Err(retry_after) = client.try_request() {
+                    debug!(parent: &self.span, ?retry_after, "client request rate exceeded");
+                    ip_limit::too_many_requests(&mut sender, retry_after);
+                    if let Some(active) = &self.metrics {
+                        active
+                            .metrics
+                            .on_status(http::StatusCode::TOO_MANY_REQUESTS);
+                    }
+                    continue;
+                }
+            }
+            let (mut head, body) = req.into_parts();
+            if let Some(addr) = self.peer_addr {
+                head.extensions.insert(PeerAddr(addr));
+            }
+            if let Some(keep_alive) = &self.keep_alive {
+                head.extensions.insert(keep_alive.rtt().clone());
+            }
+            let stats = Arc::new(StreamStats::new(
+                self.metrics.as_ref().map(|active| active.metrics.clone()),
+            ));
+            let request = Request {
+                head,
+                body: RecvStream {
+                    inner: body,
+                    timer: IdleTimer::new(self.timeouts.body_read),
+                    timed_out: false,
+                    stats: Arc::clone(&stats),
+                },
+            };
+            let response = Response {
+                status: http::StatusCode::default(),
+                headers: http::HeaderMap::default(),
+                sender,
+                budget: self.budget.clone(),
+                write_timeout: self.timeouts.write_idle,
+                expired: None,
+                stream: ActiveStream::new(&self.streams),
+                stats,
+            };
+            return Poll::Ready(Some(Ok((request, response))));
+        }
+    }
+
+    /// Returns why the connection ended, once [Conn::accept] returned `err` or `None`.
+    pub(crate) fn close_reason(&self, err: Option<h2::Error>) -> Result<(), ConnError> {
+        match err {
+            Some(err) => Err(ConnError::from(err)),
+            // Only set when the peer failed to answer a keep-alive ping.
+            None if self.is_closed => Err(ConnError::KeepAliveTimeout),
+            // `h2` reports a clean close if the peer disconnects after our `GOAWAY`.
+            None => match self.goaway_sent {
+                Some(reason) if reason != h2::Reason::NO_ERROR => Err(ConnError::GoAway {
+                    reason,
+                    origin: Origin::Local,
+                }),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Sends `GOAWAY`, gracefully if `reason` is `None`, letting in-flight streams finish,
+    /// otherwise the connection is closed with `reason`.
+    pub(crate) fn go_away(&mut self, reason: Option<h2::Reason>) {
+        match reason {
+            Some(reason) => self.inner.abrupt_shutdown(reason),
+            None => self.inner.graceful_shutdown(),
+        }
+        let reason = reason.unwrap_or(h2::Reason::NO_ERROR);
+        if self.goaway_sent != Some(reason) {
+            self.goaway_sent = Some(reason);
+            if let Some(events) = &self.events {
+                events.push(ConnEvent::GoAway(reason, Origin::Local));
+            }
+        }
+    }
+
+    /// Calls [Incoming::on_connect], and closes the connection if it is rejected.
+    pub(crate) async fn connect(&mut self, service: impl Incoming) {
+        let info = ConnInfo {
+            peer_addr: self.peer_addr,
+            proxy_header: self.proxy_header.clone(),
+            rtt: self.rtt_handle(),
+        };
+        if let Err(reason) = service.on_connect(info).await {
+            debug!(parent: &self.span, ?reason, "connection rejected");
+            self.go_away(Some(reason));
+        }
+    }
+
+    /// Accepts the next stream, and reports the protocol events to the `service` hooks meanwhile.
+    pub(crate) async fn next(
+        &mut self,
+        service: impl Incoming,
+    ) -> Option<Result<(Request, Response)>> {
+        poll_fn(move |cx| {
+            let next = self.poll_accept(cx);
+            while let Some(event) = self.events.as_ref().and_then(|events| events.pop()) {
+                match event {
+                    ConnEvent::Settings(settings) => service.on_settings(&settings),
+                    ConnEvent::GoAway(reason, origin) => service.on_goaway(reason, origin),
+                    ConnEvent::Reset(id, reason) => service.on_stream_reset(id, reason),
+                }
+            }
+            next
         })
+        .await
+    }
+
+    /// Spawns a task running the stream handler, `guard` is dropped once the handler is done.
+    ///
+    /// The stream is refused if [Builder::max_concurrent_handlers] are already running.
+    pub(crate) fn spawn<S: Incoming>(
+        &self,
+        state: S,
+        req: Request,
+        mut res: Response,
+        guard: impl Send + 'static,
+    ) {
+        // The connection holds a reference too.
+        let running = Arc::strong_count(&self.handlers) - 1;
+        if self.max_handlers.is_some_and(|max| running >= max) {
+            debug!(parent: &self.span, running, "too many concurrent handlers, refusing the stream");
+            res.sender.send_reset(h2::Reason::REFUSED_STREAM);
+            return;
+        }
+        let handlers = Arc::clone(&self.handlers);
+        let timeouts = self.timeouts;
+        #[cfg(feature = "tracing")]
+        let (span, stats) = (
+            trace::stream_span(&self.span, &req, &res),
+            Arc::clone(&res.stats),
+        );
+        let task = async move {
+            timeout::handle(state, req, res, timeouts).await;
+            drop((handlers, guard));
+        };
+        #[cfg(feature = "tracing")]
+        let task = trace::stream(span, stats, task);
+        tokio::spawn(task);
     }
 
     /// Starts serving incoming connections and handling streams using the provided callbacks.
@@ -107,7 +462,10 @@ where
     ///
     /// ### `on_close`
     ///
-    /// Called when disconnected.
+    /// Called when disconnected, [Incoming::disconnect] receives the reason.
+    ///
+    /// The returned task resolves to the same reason: `Ok(())` if the connection was closed cleanly,
+    /// or the [ConnError] that terminated it.
     ///
     /// ## Example
     ///
@@ -152,17 +510,36 @@ where
     ///     }
     /// }
     /// ```
-    pub fn incoming(mut self, _s: impl Incoming) -> task::JoinHandle<()>
+    pub fn incoming(mut self, _s: impl Incoming) -> task::JoinHandle<Result<(), ConnError>>
     where
         IO: Send + 'static,
     {
-        tokio::spawn(async move {
-            while let Some(Ok((req, res))) = self.accept().await {
-                let state = _s.clone();
-                tokio::spawn(state.stream(req, res));
-            }
-            _s.close().await;
-        })
+        #[cfg(feature = "tracing")]
+        let span = self.span.clone();
+        let task = async move {
+            self.connect(_s.clone()).await;
+            let result = loop {
+                match self.next(_s.clone()).await {
+                    Some(Ok((req, res))) => self.spawn(_s.clone(), req, res, ()),
+                    Some(Err(err)) => break self.close_reason(Some(err)),
+                    None => break self.close_reason(None),
+                }
+            };
+            _s.disconnect(&result).await;
+            result
+        };
+        #[cfg(feature = "tracing")]
+        let task = tracing::Instrument::instrument(task, span);
+        tokio::spawn(task)
+    }
+
+    /// Starts serving the connection with a [Handler], creating the connection state once
+    /// instead of cloning the service for each stream, see [Conn::incoming].
+    pub fn serve<H: Handler>(self, handler: Arc<H>) -> task::JoinHandle<Result<(), ConnError>>
+    where
+        IO: Send + 'static,
+    {
+        self.incoming(PerConn::new(handler))
     }
 }
 
@@ -176,7 +553,7 @@ impl ops::Deref for Server {
 }
 
 impl<IO> ops::Deref for Conn<IO> {
-    type Target = h2::server::Connection<IO, Bytes>;
+    type Target = h2::server::Connection<Inspect<IO>, Bytes>;
     #[inline]
     fn deref(&self) -> &Self::Target {
         &self.inner
@@ -0,0 +1,192 @@
+use super::*;
+use http::{header, HeaderValue};
+use std::{fmt::Write, pin::pin, time::Duration};
+use tokio::time::{sleep_until, Instant};
+
+/// A single Server-Sent Event.
+///
+/// ## Example
+///
+/// ```
+/// use h2x::Event;
+///
+/// let event = Event::new().event("update").id("42").data("first line\nsecond line");
+/// assert_eq!(event.as_str(), "event: update\nid: 42\ndata: first line\ndata: second line\n");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Event {
+    buf: String,
+}
+
+impl Event {
+    /// Creates an empty event.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn field(mut self, name: &str, value: &str) -> Self {
+        // Line breaks would end the field early, so they are not allowed in single line fields.
+        let value: String = value
+            .chars()
+            .filter(|ch| !matches!(ch, '\r' | '\n' | '\0'))
+            .collect();
+
+        let _ = writeln!(self.buf, "{name}: {value}");
+        self
+    }
+
+    /// Sets the event type (`event` field).
+    #[inline]
+    pub fn event(self, name: &str) -> Self {
+        self.field("event", name)
+    }
+
+    /// Sets the event ID (`id` field), which the client sends back in `last-event-id` header on reconnect.
+    #[inline]
+    pub fn id(self, id: &str) -> Self {
+        self.field("id", id)
+    }
+
+    /// Sets the reconnection time (`retry` field).
+    #[inline]
+    pub fn retry(self, duration: Duration) -> Self {
+        self.field("retry", &duration.as_millis().to_string())
+    }
+
+    /// Appends event data, multi-line data is sent as multiple `data` fields.
+    pub fn data(mut self, data: &str) -> Self {
+        for line in data.split("\r\n").flat_map(|line| line.split(['\r', '\n'])) {
+            let _ = writeln!(self.buf, "data: {line}");
+        }
+        self
+    }
+
+    /// Appends a comment line, which is ignored by the client.
+    pub fn comment(mut self, text: &str) -> Self {
+        for line in text.split("\r\n").flat_map(|line| line.split(['\r', '\n'])) {
+            let _ = writeln!(self.buf, ": {line}");
+        }
+        self
+    }
+
+    /// Returns the event fields in wire format, without the terminating blank line.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+}
+
+/// A Server-Sent Events stream, created from [Response::sse]
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+/// use std::time::Duration;
+/// use tokio::sync::mpsc::Receiver;
+///
+/// async fn events(req: Request, res: Response, mut rx: Receiver<String>) -> Result<()> {
+///     println!("last event id: {:?}", req.last_event_id());
+///     let mut sse = res.sse()?.keep_alive(Duration::from_secs(15));
+///     while let Some(msg) = sse.wait(rx.recv()).await? {
+///         sse.send(&Event::new().data(&msg)).await?;
+///     }
+///     sse.end()
+/// }
+/// ```
+pub struct EventStream {
+    responder: Responder,
+    keep_alive: Option<Duration>,
+    last_write: Instant,
+}
+
+impl Response {
+    /// Sends the response headers for a Server-Sent Events stream
+    /// (`content-type: text/event-stream`), and returns an [EventStream].
+    pub fn sse(mut self) -> Result<EventStream> {
+        self.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/event-stream"),
+        );
+        self.headers
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        Ok(EventStream {
+            responder: self.send_stream()?,
+            keep_alive: None,
+            last_write: Instant::now(),
+        })
+    }
+}
+
+impl Request {
+    /// Returns the `last-event-id` header, sent by a reconnecting Server-Sent Events client.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.head.headers.get("last-event-id")?.to_str().ok()
+    }
+}
+
+impl EventStream {
+    /// Sends a keep-alive comment whenever the stream is idle for `interval`, while waiting in [EventStream::wait]
+    ///
+    /// It prevents proxies and load balancers from closing idle connections.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    async fn write(&mut self, bytes: impl Into<Bytes>) -> Result<()> {
+        self.responder.write(bytes).await?;
+        self.last_write = Instant::now();
+        Ok(())
+    }
+
+    /// Sends an event to the client.
+    ///
+    /// It waits until the flow control window allows sending the event.
+    pub async fn send(&mut self, event: &Event) -> Result<()> {
+        let mut buf = String::with_capacity(event.buf.len() + 1);
+        buf.push_str(&event.buf);
+        buf.push('\n');
+        self.write(buf).await
+    }
+
+    /// Sends a comment to the client.
+    pub async fn comment(&mut self, text: &str) -> Result<()> {
+        self.send(&Event::new().comment(text)).await
+    }
+
+    /// Waits for `future` to complete, sending keep-alive comments while the stream is idle.
+    ///
+    /// Fails if the client closes the stream in the meantime.
+    pub async fn wait<T>(&mut self, future: impl Future<Output = T>) -> Result<T> {
+        let mut future = pin!(future);
+        loop {
+            let deadline = self.keep_alive.map(|interval| self.last_write + interval);
+            let mut sleep = pin!(deadline.map(sleep_until));
+            let is_ready = poll_fn(|cx| {
+                if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                    return Poll::Ready(Ok(Some(output)));
+                }
+                if let Poll::Ready(reason) = self.responder.inner.poll_reset(cx) {
+                    return Poll::Ready(Err(reason.map_or_else(|err| err, h2::Error::from)));
+                }
+                match sleep.as_mut().as_pin_mut().map(|sleep| sleep.poll(cx)) {
+                    Some(Poll::Ready(())) => Poll::Ready(Ok(None)),
+                    _ => Poll::Pending,
+                }
+            });
+            match is_ready.await? {
+                Some(output) => return Ok(output),
+                None => self.write(Bytes::from_static(b": keep-alive\n\n")).await?,
+            }
+        }
+    }
+
+    /// Ends the event stream.
+    #[inline]
+    pub fn end(self) -> Result<()> {
+        self.responder.end()
+    }
+}
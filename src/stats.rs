@@ -0,0 +1,71 @@
+use crate::metrics::Metrics;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use tokio::time::Instant;
+
+/// Statistics of a stream, shared by its [RecvStream](crate::RecvStream),
+/// [Response](crate::Response) and [Responder](crate::Responder).
+///
+/// The stream is recorded in the [Metrics] once all of them are dropped.
+#[derive(Debug)]
+pub(crate) struct StreamStats {
+    status: AtomicU16,
+    bytes_sent: AtomicU64,
+    started: Instant,
+    metrics: Option<Metrics>,
+}
+
+impl StreamStats {
+    pub(crate) fn new(metrics: Option<Metrics>) -> Self {
+        Self {
+            status: AtomicU16::new(0),
+            bytes_sent: AtomicU64::new(0),
+            started: Instant::now(),
+            metrics,
+        }
+    }
+
+    /// Returns the status code of the response, or `None` if no response was sent.
+    pub(crate) fn status(&self) -> Option<u16> {
+        match self.status.load(Ordering::Relaxed) {
+            0 => None,
+            status => Some(status),
+        }
+    }
+
+    pub(crate) fn set_status(&self, status: http::StatusCode) {
+        self.status.store(status.as_u16(), Ordering::Relaxed);
+    }
+
+    /// Returns the number of response body bytes sent.
+    pub(crate) fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_bytes_sent(&self, len: usize) {
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.on_bytes_sent(len);
+        }
+    }
+
+    pub(crate) fn add_bytes_received(&self, len: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_bytes_received(len);
+        }
+    }
+
+    /// Counts a write that has to wait for send capacity.
+    pub(crate) fn on_flow_control_stall(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_flow_control_stall();
+        }
+    }
+}
+
+impl Drop for StreamStats {
+    fn drop(&mut self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_stream_end(self, self.started.elapsed());
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use super::*;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::{sleep, Instant, Sleep};
+
+/// Timeouts applied to a connection and its streams, see [Builder].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Timeouts {
+    pub(crate) handshake: Option<Duration>,
+    pub(crate) header_read: Option<Duration>,
+    pub(crate) body_read: Option<Duration>,
+    pub(crate) handler: Option<Duration>,
+    pub(crate) write_idle: Option<Duration>,
+    pub(crate) conn_idle: Option<Duration>,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) max_age_grace: Option<Duration>,
+}
+
+/// Runs `future` until `deadline`, returns `None` if it elapsed first.
+pub(crate) async fn until<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// A timer that starts when an operation starts waiting, and is cleared once it makes progress.
+#[derive(Debug, Default)]
+pub(crate) struct IdleTimer {
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl IdleTimer {
+    pub(crate) fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            sleep: None,
+        }
+    }
+
+    /// Polls the timer while the operation is pending, it is ready once the timeout elapsed.
+    pub(crate) fn poll_elapsed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(timeout) = self.timeout else {
+            return Poll::Pending;
+        };
+        self.sleep
+            .get_or_insert_with(|| Box::pin(sleep(timeout)))
+            .as_mut()
+            .poll(cx)
+    }
+
+    /// Clears the timer, the operation made progress.
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        self.sleep = None;
+    }
+}
+
+/// Recycles a connection once it reached its maximum age, see [Builder::max_connection_age].
+#[derive(Debug)]
+pub(crate) struct MaxAge {
+    sleep: Pin<Box<Sleep>>,
+    grace: Option<Duration>,
+    stage: Stage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    /// The connection is younger than its maximum age.
+    Serving,
+    /// `GOAWAY` is sent, in-flight streams may finish within the grace period.
+    Draining,
+    /// The grace period elapsed, the connection must be closed.
+    Closing,
+}
+
+impl MaxAge {
+    /// The age is randomized by up to ±10%, so that connections opened at the same time are not recycled all at once.
+    pub(crate) fn new(age: Duration, grace: Option<Duration>) -> Self {
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        let age = age.mul_f64(0.9 + 0.2 * random);
+        Self {
+            sleep: Box::pin(sleep(age)),
+            grace,
+            stage: Stage::Serving,
+        }
+    }
+
+    /// Polls the timer, it is ready with the next stage once it is reached.
+    pub(crate) fn poll_stage(&mut self, cx: &mut Context<'_>) -> Poll<Stage> {
+        let is_done = match self.stage {
+            Stage::Serving => false,
+            // Without grace period, in-flight streams are never interrupted.
+            Stage::Draining => self.grace.is_none(),
+            Stage::Closing => true,
+        };
+        if is_done || self.sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        self.stage = match self.stage {
+            Stage::Serving => {
+                if let Some(grace) = self.grace {
+                    self.sleep.as_mut().reset(Instant::now() + grace);
+                    // Registers the waker for the grace period, the connection may have no other traffic.
+                    if self.sleep.as_mut().poll(cx).is_ready() {
+                        cx.waker().wake_by_ref();
+                    }
+                }
+                Stage::Draining
+            }
+            _ => Stage::Closing,
+        };
+        Poll::Ready(self.stage)
+    }
+}
+
+/// Runs the stream handler, cancelling it once the `handler` timeout elapsed.
+///
+/// If the response headers were not sent yet, `504 Gateway Timeout` is sent,
+/// otherwise the stream is reset with `CANCEL`.
+pub(crate) async fn handle<S: Incoming>(
+    state: S,
+    req: Request,
+    mut res: Response,
+    timeouts: Timeouts,
+) {
+    let Some(timeout) = timeouts.handler else {
+        return state.stream(req, res).await;
+    };
+    let expired = Arc::new(AtomicBool::new(false));
+    res.expired = Some(Arc::clone(&expired));
+
+    let mut handler = Box::pin(state.stream(req, res));
+    if tokio::time::timeout(timeout, handler.as_mut())
+        .await
+        .is_err()
+    {
+        warn!(?timeout, "handler timeout elapsed, cancelling the handler");
+        expired.store(true, Ordering::Release);
+    }
+    // The `Response` sends `504 Gateway Timeout` when it is dropped after expiry.
+    drop(handler);
+}
+
+/// Returns `true` if the handler owning the response timed out.
+pub(crate) fn is_expired(expired: &Option<Arc<AtomicBool>>) -> bool {
+    expired
+        .as_ref()
+        .is_some_and(|expired| expired.load(Ordering::Acquire))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::poll_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn max_age_wakes_after_grace() {
+        let mut max_age = MaxAge::new(Duration::from_secs(100), Some(Duration::from_secs(10)));
+        let mut stages = Vec::new();
+        // Polled once per wake-up, like `Conn::poll_liveness` on an idle connection.
+        let closed = poll_fn(|cx| match max_age.poll_stage(cx) {
+            Poll::Ready(Stage::Closing) => Poll::Ready(()),
+            Poll::Ready(stage) => {
+                stages.push(stage);
+                Poll::Pending
+            }
+            Poll::Pending => Poll::Pending,
+        });
+        let start = Instant::now();
+        assert!(tokio::time::timeout(Duration::from_secs(1000), closed)
+            .await
+            .is_ok());
+        assert_eq!(stages, [Stage::Draining]);
+        assert!(start.elapsed() >= Duration::from_secs(100));
+        assert!(start.elapsed() <= Duration::from_secs(120));
+    }
+}
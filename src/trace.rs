@@ -0,0 +1,61 @@
+// Diagnostics of the optional `tracing` feature, the macros expand to nothing without it.
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+    };
+}
+
+#[cfg(feature = "tracing")]
+use {
+    crate::{stats::StreamStats, Request, Response},
+    std::{future::Future, sync::Arc},
+    tokio::time::Instant,
+    tracing::{field::Empty, Instrument, Span},
+};
+
+/// Span of a connection, the peer is recorded once known.
+#[cfg(feature = "tracing")]
+pub(crate) fn conn_span() -> Span {
+    tracing::debug_span!("conn", peer = Empty)
+}
+
+/// Span of a stream, a child of the connection span.
+#[cfg(feature = "tracing")]
+pub(crate) fn stream_span(conn: &Span, req: &Request, res: &Response) -> Span {
+    tracing::debug_span!(
+        parent: conn,
+        "stream",
+        id = u32::from(res.stream_id()),
+        method = %req.head.method,
+        path = req.head.uri.path(),
+        status = Empty,
+        bytes = Empty,
+        duration_ms = Empty,
+    )
+}
+
+/// Runs the handler of a stream within `span`, then records the outcome of the response.
+#[cfg(feature = "tracing")]
+pub(crate) async fn stream<F: Future<Output = ()>>(
+    span: Span,
+    stats: Arc<StreamStats>,
+    handler: F,
+) {
+    let started = Instant::now();
+    handler.instrument(span.clone()).await;
+    if let Some(status) = stats.status() {
+        span.record("status", status);
+    }
+    span.record("bytes", stats.bytes_sent());
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    tracing::debug!(parent: &span, "stream closed");
+}
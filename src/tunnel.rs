@@ -0,0 +1,90 @@
+use super::*;
+use std::{io, pin::Pin};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A bidirectional byte stream established over an HTTP/2 `CONNECT` request.
+///
+/// Reading from a [Tunnel] yields the data sent by the client in the request body,
+/// and writing to it sends data back to the client as response body.
+///
+/// ## Example
+///
+/// ```no_run
+/// use h2x::*;
+/// use tokio::{io::copy_bidirectional, net::TcpStream};
+///
+/// async fn connect(req: Request, res: Response) -> std::io::Result<()> {
+///     let addr = req.uri.authority().map(|a| a.to_string()).unwrap_or_default();
+///     let mut upstream = TcpStream::connect(addr).await?;
+///     let mut tunnel = Tunnel::new(req.body, res.send_stream().map_err(std::io::Error::other)?);
+///     copy_bidirectional(&mut tunnel, &mut upstream).await?;
+///     Ok(())
+/// }
+/// ```
+pub struct Tunnel {
+    reader: BodyReader,
+    writer: BodyWriter,
+}
+
+impl Tunnel {
+    /// Creates a new [Tunnel] from the request body and the response body sender.
+    ///
+    /// The response headers must already be sent, see [Response::send_stream]
+    pub fn new(recv: RecvStream, send: Responder) -> Self {
+        Self {
+            reader: recv.into_async_read(),
+            writer: send.into_async_write(),
+        }
+    }
+
+    /// Returns the stream ID of the tunnel stream.
+    #[inline]
+    pub fn stream_id(&self) -> h2::StreamId {
+        self.reader.get_ref().stream_id()
+    }
+
+    /// Splits the [Tunnel] into its read and write halves.
+    #[inline]
+    pub fn into_split(self) -> (BodyReader, BodyWriter) {
+        (self.reader, self.writer)
+    }
+
+    /// Consumes the [Tunnel], returning the underlying request body and response body sender.
+    ///
+    /// Any data that was received but not yet read is lost.
+    pub fn into_inner(self) -> (RecvStream, Responder) {
+        (self.reader.into_inner(), self.writer.into_inner())
+    }
+}
+
+impl AsyncRead for Tunnel {
+    #[inline]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    #[inline]
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}